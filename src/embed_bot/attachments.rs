@@ -0,0 +1,330 @@
+use crate::{
+    embed_bot::config::AttachmentSettings,
+    scraper::{dash::Manifest, http::HttpClient, Post, PostSpecializedData},
+};
+use anyhow::Context;
+use reqwest::header::CONTENT_TYPE;
+use serenity::{builder::CreateAttachment, model::guild::PremiumTier};
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use url::Url;
+
+const MIB: u64 = 1024 * 1024;
+
+/// The maximum total size of the files of a single message
+pub fn upload_limit(tier: PremiumTier) -> u64 {
+    match tier {
+        PremiumTier::Tier2 => 50 * MIB,
+        PremiumTier::Tier3 => 100 * MIB,
+        _ => 10 * MIB,
+    }
+}
+
+/// Media of a post that is uploaded alongside the embed instead of being linked
+#[derive(Clone, Debug)]
+pub struct MediaAttachment {
+    pub src: Url,
+    pub file: CreateAttachment,
+}
+
+impl MediaAttachment {
+    /// The url an embed uses to reference the attachment
+    pub fn url(&self) -> String {
+        format!("attachment://{filename}", filename = self.file.filename)
+    }
+}
+
+enum Media<'p> {
+    Plain(&'p Url),
+    Dash { video_url: &'p Url, manifest_url: &'p Url },
+}
+
+impl Media<'_> {
+    /// The url the post references the media by
+    fn src(&self) -> &Url {
+        match self {
+            Media::Plain(url) => url,
+            Media::Dash { video_url, .. } => video_url,
+        }
+    }
+}
+
+fn media(post: &Post) -> Vec<Media<'_>> {
+    match &post.specialized {
+        PostSpecializedData::TextOnly => Vec::new(),
+        PostSpecializedData::Gallery { images } => images.iter().map(|i| Media::Plain(&i.url)).collect(),
+        PostSpecializedData::Image { img_url } => vec![Media::Plain(img_url)],
+        PostSpecializedData::Video { video_url } => vec![Media::Plain(video_url)],
+        PostSpecializedData::DashVideo { video_url, manifest_url } => vec![Media::Dash { video_url, manifest_url }],
+        PostSpecializedData::VideoThumbnail { thumbnail_url } => vec![Media::Plain(thumbnail_url)],
+    }
+}
+
+struct Download {
+    content_type: Option<String>,
+    data: Vec<u8>,
+}
+
+/// Temporary files that are deleted on drop
+struct TempFiles(Vec<PathBuf>);
+
+impl TempFiles {
+    fn new(names: &[&str]) -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir();
+
+        Self(
+            names
+                .iter()
+                .map(|name| dir.join(format!("embedbot-{pid}-{id}-{name}", pid = std::process::id())))
+                .collect(),
+        )
+    }
+
+    fn path(&self, ix: usize) -> &Path {
+        &self.0[ix]
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn file_extension(content_type: Option<&str>, url: &Url) -> String {
+    let from_content_type = content_type.and_then(|ct| match ct.split(';').next()?.trim() {
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "video/mp4" => Some("mp4"),
+        "video/webm" => Some("webm"),
+        _ => None,
+    });
+
+    let from_path = || {
+        let (_, ext) = url.path_segments()?.next_back()?.rsplit_once('.')?;
+        (!ext.is_empty() && ext.len() <= 5 && ext.bytes().all(|b| b.is_ascii_alphanumeric()))
+            .then(|| ext.to_ascii_lowercase())
+    };
+
+    from_content_type
+        .map(ToOwned::to_owned)
+        .or_else(from_path)
+        .unwrap_or_else(|| "bin".to_owned())
+}
+
+pub struct MediaDownloader {
+    settings: AttachmentSettings,
+    http: HttpClient,
+}
+
+impl MediaDownloader {
+    /// The media urls come from scraped posts, so only public https urls are downloaded
+    pub fn new(settings: AttachmentSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self { settings, http: http.public_only()? })
+    }
+
+    /// Downloads `url` unless it is larger than `max_size` bytes
+    async fn download(&self, url: &Url, max_size: u64) -> anyhow::Result<Option<Download>> {
+        let mut resp = self.http.get(url.clone()).await?;
+
+        if resp.content_length().is_some_and(|len| len > max_size) {
+            return Ok(None);
+        }
+
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        // the content length is not always known upfront
+        let mut data = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if (data.len() + chunk.len()) as u64 > max_size {
+                return Ok(None);
+            }
+
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(Some(Download { content_type, data }))
+    }
+
+    /// Combines a video-only and an audio-only mp4 into a single mp4
+    async fn mux(&self, video: &[u8], audio: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let files = TempFiles::new(&["video.mp4", "audio.mp4", "muxed.mp4"]);
+
+        tokio::fs::write(files.path(0), video).await?;
+        tokio::fs::write(files.path(1), audio).await?;
+
+        let output = tokio::process::Command::new(self.settings.ffmpeg.as_deref().unwrap_or(Path::new("ffmpeg")))
+            .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+            .arg(files.path(0))
+            .arg("-i")
+            .arg(files.path(1))
+            .args([
+                "-map",
+                "0:v:0",
+                "-map",
+                "1:a:0",
+                "-c",
+                "copy",
+                "-movflags",
+                "+faststart",
+            ])
+            .arg(files.path(2))
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("Unable to run ffmpeg")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "ffmpeg failed with {status}: {stderr}",
+                status = output.status,
+                stderr = String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(tokio::fs::read(files.path(2)).await?)
+    }
+
+    /// Downloads the best quality of a DASH video that fits into `max_size` bytes including its audio
+    async fn download_dash(&self, manifest_url: &Url, max_size: u64) -> anyhow::Result<Option<Download>> {
+        let mpd = self.http.get(manifest_url.clone()).await?.text().await?;
+        let manifest = Manifest::parse(manifest_url, &mpd)?;
+
+        let audio = match manifest.audio.first() {
+            Some(audio) => self.download(&audio.url, max_size).await?.map(|a| a.data),
+            None => None,
+        };
+
+        let remaining = max_size - audio.as_ref().map_or(0, |a| a.len() as u64);
+
+        for video in &manifest.video {
+            if manifest.duration.is_some_and(|d| video.estimated_size(d) > remaining) {
+                continue;
+            }
+
+            let Some(Download { data, .. }) = self.download(&video.url, remaining).await? else {
+                continue;
+            };
+
+            let Some(audio) = &audio else {
+                return Ok(Some(Download { content_type: Some("video/mp4".to_owned()), data }));
+            };
+
+            match self.mux(&data, audio).await {
+                Ok(muxed) if muxed.len() as u64 <= max_size => {
+                    return Ok(Some(Download {
+                        content_type: Some("video/mp4".to_owned()),
+                        data: muxed,
+                    }));
+                },
+                // the container overhead pushed it over the limit, try the next lower quality
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::warn!("Unable to mux {manifest_url}, attaching the video without audio: {e:#}");
+                    return Ok(Some(Download { content_type: Some("video/mp4".to_owned()), data }));
+                },
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Downloads the media of `post` as long as it fits into `upload_limit`,
+    /// media that does not fit or fails to download is left out and stays linked
+    pub async fn download_media(&self, post: &Post, upload_limit: u64) -> Vec<MediaAttachment> {
+        let mut remaining = self
+            .settings
+            .max_size_bytes
+            .map_or(upload_limit, |max| max.min(upload_limit));
+
+        let mut attachments = Vec::new();
+
+        for (ix, media) in media(post).into_iter().enumerate() {
+            let url = media.src();
+
+            let download = match &media {
+                Media::Plain(url) => self.download(url, remaining).await,
+                Media::Dash { video_url, manifest_url } => match self.download_dash(manifest_url, remaining).await {
+                    Ok(download) => Ok(download),
+                    Err(e) => {
+                        tracing::warn!("Unable to download {manifest_url}, falling back to {video_url}: {e:#}");
+                        self.download(video_url, remaining).await
+                    },
+                },
+            };
+
+            match download {
+                Ok(Some(Download { content_type, data })) => {
+                    remaining -= data.len() as u64;
+
+                    let filename = format!("media{ix}.{ext}", ext = file_extension(content_type.as_deref(), url));
+                    attachments.push(MediaAttachment {
+                        src: url.clone(),
+                        file: CreateAttachment::bytes(data, filename),
+                    });
+                },
+                Ok(None) => tracing::debug!("Not attaching {url}: exceeds upload limit"),
+                Err(e) => tracing::warn!("Unable to download {url}: {e:#}"),
+            }
+        }
+
+        attachments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn internal_urls_are_not_downloaded() {
+        let downloader = MediaDownloader::new(AttachmentSettings::default(), &HttpClient::new().unwrap()).unwrap();
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "https://127.0.0.1/a.mp4",
+            "https://localhost/a.mp4",
+        ] {
+            let url = Url::parse(url).unwrap();
+            assert!(downloader.download(&url, MIB).await.is_err(), "{url}");
+            assert!(downloader.download_dash(&url, MIB).await.is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        assert_eq!(
+            file_extension(Some("image/png"), &url("https://i.redd.it/abc.jpg")),
+            "png"
+        );
+        assert_eq!(
+            file_extension(Some("video/mp4; codecs=avc1"), &url("https://v.redd.it/abc")),
+            "mp4"
+        );
+        assert_eq!(
+            file_extension(None, &url("https://v.redd.it/abc/DASH_720.MP4?source=fallback")),
+            "mp4"
+        );
+        assert_eq!(
+            file_extension(Some("application/octet-stream"), &url("https://example.com/a")),
+            "bin"
+        );
+    }
+}
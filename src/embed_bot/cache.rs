@@ -0,0 +1,379 @@
+use crate::{
+    embed_bot::config::CacheSettings,
+    scraper::{util::url_domain_matches, Post},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::OnceCell;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedPost {
+    post: Post,
+    expires_at: SystemTime,
+}
+
+impl CachedPost {
+    fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+}
+
+/// A cache slot, while the post is still being scraped the cell is uninitialized
+/// and all concurrent requests for the same url wait for it to be filled
+struct Entry {
+    created_at: Instant,
+    post: OnceCell<CachedPost>,
+}
+
+impl Entry {
+    fn new(post: Option<CachedPost>) -> Self {
+        Self { created_at: Instant::now(), post: OnceCell::new_with(post) }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.post.get().is_some_and(CachedPost::is_expired)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub struct PostCache {
+    settings: CacheSettings,
+    entries: Mutex<HashMap<String, Arc<Entry>>>,
+    persist_lock: tokio::sync::Mutex<()>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Strips everything from the url that does not influence which post it points to
+fn normalize_url(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    if let Some(host) = url
+        .host_str()
+        .and_then(|h| h.strip_prefix("www."))
+        .map(ToOwned::to_owned)
+    {
+        let _ = url.set_host(Some(&host));
+    }
+
+    let mut query: Vec<_> = url
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_"))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    query.sort();
+
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    let path = url.path().trim_end_matches('/').to_owned();
+    url.set_path(&path);
+
+    url.into()
+}
+
+impl PostCache {
+    pub async fn load(settings: CacheSettings) -> anyhow::Result<Self> {
+        let mut entries = HashMap::new();
+
+        if let Some(path) = &settings.path {
+            match tokio::fs::read(path).await {
+                Ok(content) => match serde_json::from_slice::<HashMap<String, CachedPost>>(&content) {
+                    Ok(posts) => {
+                        entries.extend(
+                            posts
+                                .into_iter()
+                                .filter(|(_, post)| !post.is_expired())
+                                .map(|(key, post)| (key, Arc::new(Entry::new(Some(post))))),
+                        );
+                    },
+                    // most likely written by a version with a different post format, it is only a cache after all
+                    Err(e) => tracing::warn!("Discarding unreadable post cache at {}: {e}", path.display()),
+                },
+                Err(e) if e.kind() == ErrorKind::NotFound => {},
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Unable to read post cache at {}", path.display()));
+                },
+            }
+        }
+
+        Ok(Self::with_entries(settings, entries))
+    }
+
+    fn with_entries(settings: CacheSettings, entries: HashMap<String, Arc<Entry>>) -> Self {
+        Self {
+            settings,
+            entries: Mutex::new(entries),
+            persist_lock: tokio::sync::Mutex::new(()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn ttl(&self, url: &Url) -> Duration {
+        let ttl = self
+            .settings
+            .domain_ttl_secs
+            .iter()
+            .filter(|(domain, _)| url_domain_matches(url, domain))
+            // the most specific domain wins
+            .max_by_key(|(domain, _)| domain.len())
+            .map_or(self.settings.ttl_secs, |(_, ttl)| *ttl);
+
+        Duration::from_secs(ttl)
+    }
+
+    fn evict(&self, entries: &mut HashMap<String, Arc<Entry>>) {
+        entries.retain(|_, entry| !entry.is_expired());
+
+        while entries.len() > self.settings.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.created_at)
+                .map(|(key, _)| key.clone());
+
+            match oldest {
+                Some(key) => entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.settings.path else {
+            return Ok(());
+        };
+
+        let _guard = self.persist_lock.lock().await;
+
+        let content = {
+            let entries = self.entries.lock().unwrap();
+
+            let posts: HashMap<_, _> = entries
+                .iter()
+                .filter_map(|(key, entry)| Some((key, entry.post.get()?)))
+                .collect();
+
+            serde_json::to_vec(&posts)?
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// Returns the cached post for `url` or scrapes it using `scrape` if there is none.
+    /// Concurrent calls for the same url share a single invocation of `scrape`.
+    pub async fn get_or_scrape<F, Fut>(&self, url: &Url, scrape: F) -> anyhow::Result<Post>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<Post>>,
+    {
+        let key = normalize_url(url);
+
+        let entry = {
+            let mut entries = self.entries.lock().unwrap();
+
+            match entries.get(&key) {
+                Some(entry) if !entry.is_expired() => entry.clone(),
+                _ => {
+                    let entry = Arc::new(Entry::new(None));
+                    entries.insert(key.clone(), entry.clone());
+                    self.evict(&mut entries);
+                    entry
+                },
+            }
+        };
+
+        let mut scraped = false;
+        let scraped_ref = &mut scraped;
+
+        let cached = entry
+            .post
+            .get_or_try_init(|| async move {
+                *scraped_ref = true;
+                let post = scrape().await?;
+                Ok::<_, anyhow::Error>(CachedPost { post, expires_at: SystemTime::now() + self.ttl(url) })
+            })
+            .await;
+
+        let cached = match cached {
+            Ok(cached) => cached,
+            Err(e) => {
+                // a failed scrape leaves the entry uninitialized, which would never expire
+                let mut entries = self.entries.lock().unwrap();
+
+                if entries
+                    .get(&key)
+                    .is_some_and(|e| Arc::ptr_eq(e, &entry) && !e.post.initialized())
+                {
+                    entries.remove(&key);
+                }
+
+                return Err(e);
+            },
+        };
+
+        if scraped {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+
+            if let Err(e) = self.persist().await {
+                tracing::error!("Unable to persist post cache: {e:#}");
+            }
+        } else {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let CacheStats { hits, misses } = self.stats();
+        tracing::debug!(
+            "post cache {} for {key} ({hits} hits, {misses} misses)",
+            if scraped { "miss" } else { "hit" }
+        );
+
+        Ok(cached.post.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::{PostCommonData, PostSpecializedData};
+
+    fn settings() -> CacheSettings {
+        CacheSettings {
+            max_entries: 2,
+            ttl_secs: 60,
+            domain_ttl_secs: HashMap::new(),
+            path: None,
+        }
+    }
+
+    fn post(url: &Url) -> Post {
+        Post {
+            common: PostCommonData {
+                src: url.clone(),
+                origin: "test".to_owned(),
+                title: "title".to_owned(),
+                text: "".to_owned(),
+                nsfw: false,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::TextOnly,
+        }
+    }
+
+    #[test]
+    fn normalization() {
+        let url = Url::parse("https://www.reddit.com/r/a/comments/b/?utm_source=share&b=2&a=1#comment").unwrap();
+        assert_eq!(normalize_url(&url), "https://reddit.com/r/a/comments/b?a=1&b=2");
+    }
+
+    #[tokio::test]
+    async fn hit_after_miss() {
+        let cache = PostCache::load(settings()).await.unwrap();
+        let url = Url::parse("https://reddit.com/r/a").unwrap();
+
+        let first = cache.get_or_scrape(&url, || async { Ok(post(&url)) }).await.unwrap();
+        let second = cache
+            .get_or_scrape(&url, || async { panic!("post should have been cached") })
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_coalesced() {
+        let cache = PostCache::load(settings()).await.unwrap();
+        let url = Url::parse("https://reddit.com/r/a").unwrap();
+        let scrapes = AtomicU64::new(0);
+
+        let scrape = || async {
+            scrapes.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(post(&url))
+        };
+
+        let (a, b) = tokio::join!(cache.get_or_scrape(&url, scrape), cache.get_or_scrape(&url, scrape));
+
+        assert_eq!(a.unwrap(), b.unwrap());
+        assert_eq!(scrapes.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_scrapes_are_not_cached() {
+        let cache = PostCache::load(settings()).await.unwrap();
+        let url = Url::parse("https://reddit.com/r/a").unwrap();
+
+        let failed = cache
+            .get_or_scrape(&url, || async { anyhow::bail!("unreachable") })
+            .await;
+        assert!(failed.is_err());
+        assert!(cache.entries.lock().unwrap().is_empty());
+
+        cache.get_or_scrape(&url, || async { Ok(post(&url)) }).await.unwrap();
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oldest_entries_are_evicted() {
+        let cache = PostCache::load(settings()).await.unwrap();
+
+        for path in ["a", "b", "c"] {
+            let url = Url::parse(&format!("https://reddit.com/r/{path}")).unwrap();
+            cache.get_or_scrape(&url, || async { Ok(post(&url)) }).await.unwrap();
+        }
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key("https://reddit.com/r/a"));
+    }
+
+    #[test]
+    fn ttl_per_domain() {
+        let mut settings = settings();
+        settings.domain_ttl_secs.insert("reddit.com".to_owned(), 5);
+        settings.domain_ttl_secs.insert("old.reddit.com".to_owned(), 10);
+
+        let cache = PostCache::with_entries(settings, HashMap::new());
+
+        let ttl = |url: &str| cache.ttl(&Url::parse(url).unwrap());
+
+        assert_eq!(ttl("https://old.reddit.com/r/a"), Duration::from_secs(10));
+        assert_eq!(ttl("https://new.reddit.com/r/a"), Duration::from_secs(5));
+        assert_eq!(ttl("https://reddit.com/r/a"), Duration::from_secs(5));
+        assert_eq!(ttl("https://notreddit.com/r/a"), Duration::from_secs(60));
+    }
+}
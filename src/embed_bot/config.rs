@@ -1,20 +1,50 @@
 use crate::scraper;
 use serde::{Deserialize, Serialize};
-use std::fmt::{Debug, Formatter};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    path::PathBuf,
+};
 
 #[derive(Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct Config {
-    pub discord_token: String,
+    /// only needed to run the bot, not for the debugging commands
+    pub discord_token: Option<String>,
     pub embed_behaviour: EmbedBehaviours,
+
+    #[cfg(feature = "implicit-auto-embed")]
+    #[serde(default)]
+    pub auto_embed: AutoEmbedSettings,
+
+    /// if present, embeds of auto-embedded messages that are kept follow edits and deletions of those messages
+    #[cfg(feature = "implicit-auto-embed")]
+    pub tracking: Option<TrackingSettings>,
+
+    pub cache: Option<CacheSettings>,
+
+    /// if present, media is uploaded as attachments instead of being linked
+    pub attachments: Option<AttachmentSettings>,
+
+    /// if present, the per guild and channel settings changed via `/embedbot-config` are persisted in this json file
+    pub settings_path: Option<PathBuf>,
+
     pub modules: Option<Modules>,
 }
 
 impl Debug for Config {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Config")
-            .field("discord_token", &"[REDACTED]")
-            .field("embed_behaviour", &self.embed_behaviour)
+        let mut s = f.debug_struct("Config");
+        s.field("discord_token", &"[REDACTED]")
+            .field("embed_behaviour", &self.embed_behaviour);
+
+        #[cfg(feature = "implicit-auto-embed")]
+        s.field("auto_embed", &self.auto_embed)
+            .field("tracking", &self.tracking);
+
+        s.field("cache", &self.cache)
+            .field("attachments", &self.attachments)
+            .field("settings_path", &self.settings_path)
             .field("modules", &self.modules)
             .finish()
     }
@@ -32,6 +62,127 @@ pub struct EmbedBehaviour {
 pub struct EmbedBehaviours {
     pub nsfw: EmbedBehaviour,
     pub spoiler: EmbedBehaviour,
+
+    #[serde(default)]
+    pub nsfw_policy: NsfwPolicy,
+
+    /// revealing a nsfw post in an age-restricted channel shows it to everyone instead of only the clicking user
+    #[serde(default)]
+    pub reveal_publicly_in_nsfw_channels: bool,
+
+    #[serde(default)]
+    pub style: EmbedStyle,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NsfwPolicy {
+    /// nsfw posts are only embedded fully according to `nsfw`
+    #[default]
+    Static,
+
+    /// nsfw posts are additionally embedded fully in channels marked as age-restricted
+    ChannelAware,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmbedStyle {
+    /// title, text and comment of the post
+    #[default]
+    Full,
+
+    /// only the title of the post, leaving out its text and comment
+    Compact,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoEmbedMode {
+    /// the message is deleted and reposted along with the embeds, requires the Manage Messages permission
+    #[default]
+    Repost,
+
+    /// the embeds reply to the message and its own link previews are suppressed, requires the Manage Messages
+    /// permission
+    SuppressEmbeds,
+
+    /// the embeds reply to the message, which is left as it is
+    Reply,
+}
+
+/// also applies to the message context menu command, which exists without auto-embedding
+pub const DEFAULT_MAX_URLS_PER_MESSAGE: usize = 5;
+
+#[cfg(feature = "implicit-auto-embed")]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AutoEmbedSettings {
+    /// urls beyond this count are not embedded, to keep a single message from flooding the channel
+    #[serde(default = "AutoEmbedSettings::default_max_urls_per_message")]
+    pub max_urls_per_message: usize,
+
+    /// reposts are sent via a channel webhook under the nickname and avatar of the original author,
+    /// requires the Manage Webhooks permission
+    #[serde(default)]
+    pub use_webhooks: bool,
+
+    /// what happens to the message containing the urls, unless overridden for a guild or channel
+    #[serde(default)]
+    pub mode: AutoEmbedMode,
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl AutoEmbedSettings {
+    fn default_max_urls_per_message() -> usize {
+        DEFAULT_MAX_URLS_PER_MESSAGE
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl Default for AutoEmbedSettings {
+    fn default() -> Self {
+        Self {
+            max_urls_per_message: Self::default_max_urls_per_message(),
+            use_webhooks: false,
+            mode: AutoEmbedMode::default(),
+        }
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TrackingSettings {
+    /// the oldest messages stop being tracked beyond this count
+    pub max_entries: usize,
+
+    /// if present the tracked messages are persisted in this file so that they survive restarts
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CacheSettings {
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+
+    /// overrides `ttl_secs` for urls on the given domains (and their subdomains)
+    #[serde(default)]
+    pub domain_ttl_secs: HashMap<String, u64>,
+
+    /// if present the cache is persisted in this file so that it survives restarts
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AttachmentSettings {
+    /// caps the total size of the attachments of a message below the upload limit of the guild
+    pub max_size_bytes: Option<u64>,
+
+    /// used to combine separate video and audio tracks, looked up in `PATH` if not set
+    pub ffmpeg: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -45,4 +196,16 @@ pub struct Modules {
 
     #[cfg(feature = "twitter")]
     pub twitter: Option<scraper::twitter::ApiSettings>,
+
+    #[cfg(feature = "bluesky")]
+    pub bluesky: Option<scraper::bluesky::ApiSettings>,
+
+    #[cfg(feature = "mastodon")]
+    pub mastodon: Option<scraper::mastodon::ApiSettings>,
+
+    #[cfg(feature = "oembed")]
+    pub oembed: Option<scraper::oembed::ApiSettings>,
+
+    #[cfg(feature = "opengraph")]
+    pub opengraph: Option<scraper::opengraph::ApiSettings>,
 }
@@ -0,0 +1,261 @@
+use crate::embed_bot::{
+    parse_option,
+    settings::{GuildSettings, Overrides, Settings, SETTING_NAMES},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::{
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed},
+    model::{
+        application::{CommandData, CommandDataOption, CommandDataOptionValue, CommandOptionType, CommandType},
+        id::ChannelId,
+        Permissions,
+    },
+};
+
+pub const NAME: &str = "embedbot-config";
+
+pub enum Action {
+    Show,
+    Set(Overrides),
+
+    /// resets a single setting if given, otherwise all of them
+    Reset(Option<String>),
+}
+
+pub struct ConfigCommand {
+    /// the whole guild is targeted if not set
+    pub channel: Option<ChannelId>,
+    pub action: Action,
+}
+
+fn channel_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Channel,
+        "channel",
+        "only apply to this channel instead of the whole server",
+    )
+    .required(false)
+}
+
+fn bool_option(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::Boolean, name, description).required(false)
+}
+
+/// `modules` are the names of the registered scraper modules
+pub fn create_command<'m>(modules: impl IntoIterator<Item = &'m str>) -> CreateCommand {
+    let module_option = modules.into_iter().fold(
+        CreateCommandOption::new(CommandOptionType::String, "name", "the module").required(true),
+        |option, name| option.add_string_choice(name, name),
+    );
+
+    let setting_option = SETTING_NAMES.iter().fold(
+        CreateCommandOption::new(CommandOptionType::String, "setting", "only reset this setting").required(false),
+        |option, name| option.add_string_choice(*name, *name),
+    );
+
+    CreateCommand::new(NAME)
+        .kind(CommandType::ChatInput)
+        .description("configure embedbot for this server")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .dm_permission(false)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "show", "show the current settings")
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Channel,
+                        "channel",
+                        "show the settings of this channel",
+                    )
+                    .required(false),
+                ),
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "set", "change settings")
+                .add_sub_option(channel_option())
+                .add_sub_option(bool_option("embed-nsfw", "embed nsfw posts fully by default"))
+                .add_sub_option(bool_option("embed-spoiler", "embed spoilered posts fully by default"))
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "nsfw-policy",
+                        "whether nsfw posts are embedded fully in age-restricted channels",
+                    )
+                    .add_string_choice("static", "static")
+                    .add_string_choice("channel-aware", "channel-aware")
+                    .required(false),
+                )
+                .add_sub_option(bool_option("auto-embed", "embed urls in messages automatically"))
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "style", "how much of a post is shown")
+                        .add_string_choice("full", "full")
+                        .add_string_choice("compact", "compact")
+                        .required(false),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "auto-embed-mode",
+                        "what happens to automatically embedded messages",
+                    )
+                    .add_string_choice("repost", "repost")
+                    .add_string_choice("suppress-embeds", "suppress-embeds")
+                    .add_string_choice("reply", "reply")
+                    .required(false),
+                ),
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "module", "enable or disable a module")
+                .add_sub_option(module_option)
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "whether to use the module")
+                        .required(true),
+                )
+                .add_sub_option(channel_option()),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "reset",
+                "go back to the defaults of the bot, or of the server for a channel",
+            )
+            .add_sub_option(channel_option())
+            .add_sub_option(setting_option),
+        )
+}
+
+/// Parses a choice by the name it has in the settings file
+fn parse_choice<T: DeserializeOwned>(value: Option<&str>) -> anyhow::Result<Option<T>> {
+    value
+        .map(|value| serde_json::from_value(value.into()).map_err(|_| anyhow::anyhow!("Invalid choice {value}")))
+        .transpose()
+}
+
+/// The name of a choice in the settings file
+fn choice_name<T: Serialize>(value: T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => "?".to_owned(),
+    }
+}
+
+impl ConfigCommand {
+    pub fn parse(data: &CommandData) -> anyhow::Result<Self> {
+        let Some(CommandDataOption { name, value: CommandDataOptionValue::SubCommand(options), .. }) =
+            data.options.first()
+        else {
+            anyhow::bail!("Missing subcommand");
+        };
+
+        let channel = parse_option(options, "channel", CommandDataOptionValue::as_channel_id)?;
+
+        let action = match name.as_str() {
+            "show" => Action::Show,
+            "set" => {
+                let change = Overrides {
+                    embed_nsfw: parse_option(options, "embed-nsfw", |x| x.as_bool())?,
+                    embed_spoiler: parse_option(options, "embed-spoiler", |x| x.as_bool())?,
+                    nsfw_policy: parse_choice(parse_option(options, "nsfw-policy", |x| x.as_str())?)?,
+                    auto_embed: parse_option(options, "auto-embed", |x| x.as_bool())?,
+                    style: parse_choice(parse_option(options, "style", |x| x.as_str())?)?,
+                    auto_embed_mode: parse_choice(parse_option(options, "auto-embed-mode", |x| x.as_str())?)?,
+                    ..Default::default()
+                };
+
+                if change.is_empty() {
+                    anyhow::bail!("Nothing to change");
+                }
+
+                Action::Set(change)
+            },
+            "module" => {
+                let module = parse_option(options, "name", |x| x.as_str())?
+                    .ok_or_else(|| anyhow::anyhow!("Parameter name must be present"))?;
+                let enabled = parse_option(options, "enabled", |x| x.as_bool())?
+                    .ok_or_else(|| anyhow::anyhow!("Parameter enabled must be present"))?;
+
+                let change = Overrides {
+                    modules: [(module.to_owned(), enabled)].into(),
+                    ..Default::default()
+                };
+
+                Action::Set(change)
+            },
+            "reset" => Action::Reset(parse_option(options, "setting", |x| x.as_str())?.map(ToOwned::to_owned)),
+            _ => anyhow::bail!("Unknown subcommand {name}"),
+        };
+
+        Ok(Self { channel, action })
+    }
+}
+
+fn describe_overrides(o: &Overrides) -> String {
+    let mut lines = Vec::new();
+
+    let mut line = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            lines.push(format!("{name}: `{value}`"));
+        }
+    };
+
+    line("embed-nsfw", o.embed_nsfw.map(|x| x.to_string()));
+    line("embed-spoiler", o.embed_spoiler.map(|x| x.to_string()));
+    line("nsfw-policy", o.nsfw_policy.map(choice_name));
+    line("auto-embed", o.auto_embed.map(|x| x.to_string()));
+    line("style", o.style.map(choice_name));
+    line("auto-embed-mode", o.auto_embed_mode.map(choice_name));
+
+    for (module, enabled) in &o.modules {
+        line(
+            &format!("module {module}"),
+            Some(if *enabled { "enabled" } else { "disabled" }.to_owned()),
+        );
+    }
+
+    if lines.is_empty() {
+        "none".to_owned()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// Shows the effective settings in `channel` along with all overrides of the guild
+pub fn describe<'m>(
+    guild: &GuildSettings,
+    channel: ChannelId,
+    settings: &Settings,
+    modules: impl IntoIterator<Item = &'m str>,
+) -> CreateEmbed {
+    let disabled_modules: Vec<_> = modules.into_iter().filter(|m| !settings.module_enabled(m)).collect();
+
+    let effective = format!(
+        "in <#{channel}>\nembed-nsfw: `{}`\nembed-spoiler: `{}`\nnsfw-policy: `{}`\nauto-embed: `{}`\nstyle: `{}`\n\
+         auto-embed-mode: `{}`\ndisabled modules: `{}`",
+        settings.embed_nsfw,
+        settings.embed_spoiler,
+        choice_name(settings.nsfw_policy),
+        settings.auto_embed,
+        choice_name(settings.style),
+        choice_name(settings.auto_embed_mode),
+        if disabled_modules.is_empty() {
+            "none".to_owned()
+        } else {
+            disabled_modules.join(", ")
+        },
+    );
+
+    let mut e = CreateEmbed::new()
+        .title("embedbot settings")
+        .field("Effective settings", effective, false)
+        .field("Server overrides", describe_overrides(&guild.guild), false);
+
+    // channel mentions are only rendered in field values, discord allows 25 fields in total
+    for (channel, overrides) in guild.channels.iter().take(23) {
+        e = e.field(
+            "Channel overrides",
+            format!("<#{channel}>\n{}", describe_overrides(overrides)),
+            false,
+        );
+    }
+
+    e
+}
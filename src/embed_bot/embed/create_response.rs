@@ -1,13 +1,28 @@
-use serenity::builder::{CreateEmbed, CreateInteractionResponseMessage, CreateMessage};
+#[cfg(feature = "implicit-auto-embed")]
+use serenity::builder::ExecuteWebhook;
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateEmbed, CreateInteractionResponseMessage, CreateMessage, EditAttachments,
+    EditInteractionResponse, EditMessage,
+};
 
-pub trait CreateResponse: Default {
+pub trait CreateResponse {
+    fn new() -> Self;
     fn content(self, s: impl Into<String>) -> Self;
     fn add_embed(self, e: CreateEmbed) -> Self;
+    fn add_file(self, file: CreateAttachment) -> Self;
+    fn components(self, components: Vec<CreateActionRow>) -> Self;
 }
 
 macro_rules! impl_create_response {
     ($builder:ty) => {
+        impl_create_response!($builder, <$builder>::new(), add_file);
+    };
+    ($builder:ty, $new:expr, $add_file:ident) => {
         impl CreateResponse for $builder {
+            fn new() -> Self {
+                $new
+            }
+
             fn content(self, s: impl Into<String>) -> Self {
                 self.content(s)
             }
@@ -15,11 +30,77 @@ macro_rules! impl_create_response {
             fn add_embed(self, e: CreateEmbed) -> Self {
                 self.add_embed(e)
             }
+
+            fn add_file(self, file: CreateAttachment) -> Self {
+                self.$add_file(file)
+            }
+
+            fn components(self, components: Vec<CreateActionRow>) -> Self {
+                self.components(components)
+            }
         }
     };
 }
 
 impl_create_response!(CreateInteractionResponseMessage);
 
-#[cfg(feature = "implicit-auto-embed")]
+// edits only touch the fields that are set, so explicitly clear whatever a previous state of the response displayed
+impl_create_response!(
+    EditInteractionResponse,
+    EditInteractionResponse::new()
+        .content("")
+        .embeds(Vec::new())
+        .components(Vec::new())
+        .clear_attachments(),
+    new_attachment
+);
+
 impl_create_response!(CreateMessage);
+
+impl_create_response!(
+    EditMessage,
+    EditMessage::new()
+        .content("")
+        .embeds(Vec::new())
+        .components(Vec::new())
+        .attachments(EditAttachments::new()),
+    new_attachment
+);
+
+/// `ExecuteWebhook` can only set all embeds at once, so they are collected until the message is complete
+#[cfg(feature = "implicit-auto-embed")]
+pub struct WebhookMessage {
+    builder: ExecuteWebhook,
+    embeds: Vec<CreateEmbed>,
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl CreateResponse for WebhookMessage {
+    fn new() -> Self {
+        Self { builder: ExecuteWebhook::new(), embeds: Vec::new() }
+    }
+
+    fn content(self, s: impl Into<String>) -> Self {
+        Self { builder: self.builder.content(s), ..self }
+    }
+
+    fn add_embed(mut self, e: CreateEmbed) -> Self {
+        self.embeds.push(e);
+        self
+    }
+
+    fn add_file(self, file: CreateAttachment) -> Self {
+        Self { builder: self.builder.add_file(file), ..self }
+    }
+
+    fn components(self, components: Vec<CreateActionRow>) -> Self {
+        Self { builder: self.builder.components(components), ..self }
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl From<WebhookMessage> for ExecuteWebhook {
+    fn from(message: WebhookMessage) -> Self {
+        message.builder.embeds(message.embeds)
+    }
+}
@@ -2,20 +2,75 @@ pub mod create_response;
 mod util;
 
 use crate::{
-    embed_bot::embed::create_response::CreateResponse,
-    scraper::{Comment, Post, PostCommonData, PostSpecializedData},
+    embed_bot::{attachments::MediaAttachment, config::EmbedStyle, embed::create_response::CreateResponse},
+    scraper::{Comment, GalleryImage, Post, PostCommonData, PostSpecializedData},
 };
 use serenity::{
-    builder::{CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter},
-    model::user::User,
+    builder::{CreateActionRow, CreateButton, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter},
+    model::{
+        application::{ActionRow, ActionRowComponent, ButtonKind, ButtonStyle},
+        id::UserId,
+    },
 };
 use url::Url;
 
+/// discord allows at most 10 embeds per message, one of which is taken by the post itself
+const GALLERY_PAGE_SIZE: usize = 9;
+
+/// prefix of the custom id of the gallery navigation buttons, followed by the page the button leads to
+const GALLERY_PAGE_ID_PREFIX: &str = "gallery-page:";
+
+/// prefix of the custom id of the reveal button, followed by the reason the post is hidden
+const REVEAL_ID_PREFIX: &str = "reveal:";
+
+/// prefix of the custom id of the delete button, followed by the id of the only user allowed to use it
+const DELETE_ID_PREFIX: &str = "delete:";
+
+/// Why the content of a post is hidden behind a warning
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HiddenReason {
+    Nsfw,
+    Spoiler,
+}
+
+impl HiddenReason {
+    fn id(self) -> &'static str {
+        match self {
+            Self::Nsfw => "nsfw",
+            Self::Spoiler => "spoiler",
+        }
+    }
+}
+
 #[derive(Debug, Default)]
-pub struct EmbedOptions<'comment> {
-    pub comment: Option<&'comment str>,
+pub struct EmbedOptions<'opts> {
+    pub comment: Option<&'opts str>,
     pub embed_nsfw: bool,
     pub embed_spoiler: bool,
+    pub style: EmbedStyle,
+
+    /// the user that may delete the embed, there is no delete button if not set
+    pub owner: Option<UserId>,
+
+    /// media of the post that is uploaded instead of linked
+    pub attachments: &'opts [MediaAttachment],
+}
+
+impl EmbedOptions<'_> {
+    /// Whether the content of `post` is hidden behind a nsfw or spoiler warning
+    pub fn hides(&self, post: &Post) -> bool {
+        (post.common.nsfw && !self.embed_nsfw) || (post.common.spoiler && !self.embed_spoiler)
+    }
+
+    fn attachment(&self, url: &Url) -> Option<&MediaAttachment> {
+        self.attachments.iter().find(|a| &a.src == url)
+    }
+
+    /// The url to display `url` from, which is the attachment if there is one
+    fn media_url(&self, url: &Url) -> String {
+        self.attachment(url)
+            .map_or_else(|| url.to_string(), MediaAttachment::url)
+    }
 }
 
 fn include_comment(e: CreateEmbed, comment: &Comment) -> CreateEmbed {
@@ -23,16 +78,16 @@ fn include_comment(e: CreateEmbed, comment: &Comment) -> CreateEmbed {
     e.field(name, util::escape_markdown(&comment.text), true)
 }
 
-fn include_author_comment(e: CreateEmbed, u: &User, comment: &str) -> CreateEmbed {
-    let title = format!("Comment by {author}", author = u.display_name());
+fn include_author_comment(e: CreateEmbed, author: &str, comment: &str) -> CreateEmbed {
+    let title = format!("Comment by {author}");
     e.field(title, comment, false)
 }
 
-fn base_embed(author: &User, comment: Option<&str>, post: &PostCommonData) -> CreateEmbed {
+fn base_embed(author: &str, comment: Option<&str>, post: &PostCommonData) -> CreateEmbed {
     let mut e = CreateEmbed::new()
         .title(util::fmt_title(post))
         .description(util::limit_descr_len(&post.text))
-        .author(CreateEmbedAuthor::new(author.display_name()))
+        .author(CreateEmbedAuthor::new(author))
         .url(post.src.as_str());
 
     if let Some(comment) = comment {
@@ -46,15 +101,149 @@ fn base_embed(author: &User, comment: Option<&str>, post: &PostCommonData) -> Cr
     e
 }
 
-fn manual_embed(author: &User, discord_comment: Option<&str>, post: &PostCommonData, embed_urls: &[Url]) -> String {
-    let discord_comment = discord_comment
-        .map(|c| {
-            format!(
-                "**Comment By {author}:**\n{comment}\n\n",
-                author = author.display_name(),
-                comment = c
-            )
+fn gallery_image_embed(image: &GalleryImage, ix: usize, count: usize, opts: &EmbedOptions) -> CreateEmbed {
+    let footer = match &image.caption {
+        Some(caption) => format!("Image {}/{count}: {caption}", ix + 1),
+        None => format!("Image {}/{count}", ix + 1),
+    };
+
+    let e = CreateEmbed::new()
+        .image(opts.media_url(&image.url))
+        .footer(CreateEmbedFooter::new(footer));
+
+    match &image.link {
+        Some(link) => e.description(link.as_str()),
+        None => e,
+    }
+}
+
+fn gallery_buttons(page: usize, page_count: usize) -> CreateActionRow {
+    let nav_button = |target: usize, label: &str, disabled: bool| {
+        CreateButton::new(format!("{GALLERY_PAGE_ID_PREFIX}{target}"))
+            .label(label)
+            .style(ButtonStyle::Secondary)
+            .disabled(disabled)
+    };
+
+    CreateActionRow::Buttons(vec![
+        nav_button(page.saturating_sub(1), "◀", page == 0),
+        CreateButton::new("gallery-page-indicator")
+            .label(format!("{}/{page_count}", page + 1))
+            .style(ButtonStyle::Secondary)
+            .disabled(true),
+        nav_button(page + 1, "▶", page + 1 >= page_count),
+    ])
+}
+
+fn add_gallery_page<R: CreateResponse>(
+    response: R,
+    base: CreateEmbed,
+    images: &[GalleryImage],
+    page: usize,
+    opts: &EmbedOptions,
+) -> (R, Vec<CreateActionRow>) {
+    let page_count = images.len().div_ceil(GALLERY_PAGE_SIZE);
+    let page = page.min(page_count.saturating_sub(1));
+
+    let response = images
+        .iter()
+        .enumerate()
+        .skip(page * GALLERY_PAGE_SIZE)
+        .take(GALLERY_PAGE_SIZE)
+        .fold(response.add_embed(base), |response, (ix, image)| {
+            response.add_embed(gallery_image_embed(image, ix, images.len(), opts))
+        });
+
+    if page_count > 1 {
+        (response, vec![gallery_buttons(page, page_count)])
+    } else {
+        (response, Vec::new())
+    }
+}
+
+/// Whether the post is a gallery that does not fit into a single message
+pub fn is_paginated(post: &Post) -> bool {
+    matches!(&post.specialized, PostSpecializedData::Gallery { images } if images.len() > GALLERY_PAGE_SIZE)
+}
+
+/// Parses the page a gallery navigation button leads to from its custom id
+pub fn parse_gallery_page(custom_id: &str) -> Option<usize> {
+    custom_id.strip_prefix(GALLERY_PAGE_ID_PREFIX)?.parse().ok()
+}
+
+/// Shows `page` of the gallery `post` below `base`, which is the embed that was sent for the post originally
+pub fn gallery_page<R: CreateResponse>(
+    post: &Post,
+    base: CreateEmbed,
+    page: usize,
+    owner: Option<UserId>,
+) -> Option<R> {
+    match &post.specialized {
+        PostSpecializedData::Gallery { images } => {
+            let opts = EmbedOptions { owner, ..Default::default() };
+            let (response, rows) = add_gallery_page(R::new(), base, images, page, &opts);
+            Some(with_components(response, rows, &opts))
+        },
+        _ => None,
+    }
+}
+
+fn reveal_button(reason: HiddenReason) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(format!("{REVEAL_ID_PREFIX}{}", reason.id()))
+        .label("Reveal")
+        .style(ButtonStyle::Secondary)])
+}
+
+fn delete_button(owner: UserId) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(format!("{DELETE_ID_PREFIX}{owner}"))
+        .emoji('🗑')
+        .style(ButtonStyle::Secondary)])
+}
+
+/// Sets `rows` as the components of the response, followed by the delete button if there is an owner
+fn with_components<R: CreateResponse>(response: R, mut rows: Vec<CreateActionRow>, opts: &EmbedOptions) -> R {
+    if let Some(owner) = opts.owner {
+        rows.push(delete_button(owner));
+    }
+
+    if rows.is_empty() {
+        response
+    } else {
+        response.components(rows)
+    }
+}
+
+/// Parses the user allowed to delete the embed from the custom id of a delete button
+pub fn parse_delete(custom_id: &str) -> Option<UserId> {
+    custom_id.strip_prefix(DELETE_ID_PREFIX)?.parse().ok()
+}
+
+/// The user allowed to delete an embed, found by the delete button among its `components`
+pub fn owner(components: &[ActionRow]) -> Option<UserId> {
+    components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::Button(button) => match &button.data {
+                ButtonKind::NonLink { custom_id, .. } => parse_delete(custom_id),
+                _ => None,
+            },
+            _ => None,
         })
+}
+
+/// Parses the reason the post was hidden for from the custom id of a reveal button
+pub fn parse_reveal(custom_id: &str) -> Option<HiddenReason> {
+    match custom_id.strip_prefix(REVEAL_ID_PREFIX)? {
+        "nsfw" => Some(HiddenReason::Nsfw),
+        "spoiler" => Some(HiddenReason::Spoiler),
+        _ => None,
+    }
+}
+
+fn manual_embed(author: &str, discord_comment: Option<&str>, post: &PostCommonData, embed_urls: &[Url]) -> String {
+    let discord_comment = discord_comment
+        .map(|c| format!("**Comment By {author}:**\n{comment}\n\n", author = author, comment = c))
         .unwrap_or_default();
 
     let post_comment = post
@@ -73,7 +262,7 @@ fn manual_embed(author: &User, discord_comment: Option<&str>, post: &PostCommonD
 
     format!(
         ">>> **{author}**\nSource: <{src}>\nEmbedURL: {embed_url}\n\n{discord_comment}{post_comment}{title}\n\n{text}",
-        author = author.display_name(),
+        author = author,
         src = &post.src,
         embed_url = urls,
         title = util::fmt_title(post),
@@ -83,75 +272,151 @@ fn manual_embed(author: &User, discord_comment: Option<&str>, post: &PostCommonD
     )
 }
 
-pub fn embed<R: CreateResponse>(post: &Post, user: &User, opts: &EmbedOptions) -> R {
-    let response = R::default();
+/// Embeds `post` on behalf of the user named `author`
+pub fn embed<R: CreateResponse>(post: &Post, author: &str, opts: &EmbedOptions) -> R {
+    let (response, rows) = embed_without_components(post, author, opts);
+    with_components(response, rows, opts)
+}
+
+fn embed_without_components<R: CreateResponse>(
+    post: &Post,
+    author: &str,
+    opts: &EmbedOptions,
+) -> (R, Vec<CreateActionRow>) {
+    let response = R::new();
+
+    let compact;
+    let common = match opts.style {
+        EmbedStyle::Full => &post.common,
+        EmbedStyle::Compact => {
+            compact = PostCommonData { text: String::new(), comment: None, ..post.common.clone() };
+            &compact
+        },
+    };
 
     if post.common.nsfw && !opts.embed_nsfw {
-        response.add_embed({
+        let response = response.add_embed({
             let mut e = CreateEmbed::new()
                 .title(util::fmt_title(&post.common))
                 .description("Warning NSFW: Click to view content")
-                .author(CreateEmbedAuthor::new(user.display_name()))
+                .author(CreateEmbedAuthor::new(author))
                 .url(post.common.src.as_str());
 
             if let Some(comment) = &opts.comment {
-                e = include_author_comment(e, user, comment);
+                e = include_author_comment(e, author, comment);
             }
 
             e
-        })
+        });
+
+        (response, vec![reveal_button(HiddenReason::Nsfw)])
     } else if post.common.spoiler && !opts.embed_spoiler {
-        response.add_embed({
+        let response = response.add_embed({
             let mut e = CreateEmbed::new()
                 .title(util::fmt_title(&post.common))
                 .description("Spoiler: Click to view content")
-                .author(CreateEmbedAuthor::new(user.display_name()))
+                .author(CreateEmbedAuthor::new(author))
                 .url(post.common.src.as_str());
 
             if let Some(comment) = &opts.comment {
-                e = include_author_comment(e, user, comment);
+                e = include_author_comment(e, author, comment);
             }
 
-            if let Some(comment) = &post.common.comment {
+            if let Some(comment) = &common.comment {
                 e = include_comment(e, comment);
             }
 
             e
-        })
+        });
+
+        (response, vec![reveal_button(HiddenReason::Spoiler)])
     } else {
-        match &post.specialized {
-            PostSpecializedData::TextOnly => response.add_embed(base_embed(user, opts.comment, &post.common)),
+        let response = opts
+            .attachments
+            .iter()
+            .fold(response, |response, a| response.add_file(a.file.clone()));
+
+        let response = match &post.specialized {
+            PostSpecializedData::TextOnly => response.add_embed(base_embed(author, opts.comment, common)),
             PostSpecializedData::Image { img_url } => {
-                response.add_embed(base_embed(user, opts.comment, &post.common).image(img_url.as_str()))
+                response.add_embed(base_embed(author, opts.comment, common).image(opts.media_url(img_url)))
+            },
+            // the only kind of post that comes with its own components
+            PostSpecializedData::Gallery { images } => {
+                return add_gallery_page(response, base_embed(author, opts.comment, common), images, 0, opts);
             },
-            PostSpecializedData::Gallery { img_urls } => {
-                let base = response.add_embed(base_embed(user, opts.comment, &post.common));
-
-                img_urls.iter().enumerate().fold(base, |response, (ix, img_url)| {
-                    response.add_embed(
-                        CreateEmbed::new()
-                            .image(img_url.as_str())
-                            .footer(CreateEmbedFooter::new(format!("Image {}/{}", ix + 1, img_urls.len()))),
-                    )
-                })
+            // discord plays uploaded videos inline, right above the embed
+            PostSpecializedData::Video { video_url } | PostSpecializedData::DashVideo { video_url, .. }
+                if opts.attachment(video_url).is_some() =>
+            {
+                response.add_embed(base_embed(author, opts.comment, common))
             },
-            PostSpecializedData::Video { video_url } => response.content(manual_embed(
-                user,
-                opts.comment,
-                &post.common,
-                std::slice::from_ref(video_url),
-            )),
+            PostSpecializedData::Video { video_url } | PostSpecializedData::DashVideo { video_url, .. } => response
+                .content(manual_embed(
+                    author,
+                    opts.comment,
+                    common,
+                    std::slice::from_ref(video_url),
+                )),
             PostSpecializedData::VideoThumbnail { thumbnail_url } => response.add_embed(
-                base_embed(user, opts.comment, &post.common)
-                    .image(thumbnail_url.as_str())
+                base_embed(author, opts.comment, common)
+                    .image(opts.media_url(thumbnail_url))
                     .footer(CreateEmbedFooter::new(
                         "This was originally a video. Click title to watch on website.",
                     )),
             ),
-        }
+        };
+
+        (response, Vec::new())
     }
 }
 
+pub fn progress<R: CreateResponse>(url: &Url) -> R {
+    R::new().add_embed(
+        CreateEmbed::new()
+            .title(":hourglass: Embedding post")
+            .description(url.as_str()),
+    )
+}
+
 pub fn error<R: CreateResponse, S: Into<String>>(msg: S) -> R {
-    R::default().add_embed(CreateEmbed::new().title(":x: Error").description(msg))
+    R::new().add_embed(CreateEmbed::new().title(":x: Error").description(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::builder::CreateInteractionResponseMessage;
+
+    #[test]
+    fn gallery_page_ids() {
+        assert_eq!(parse_gallery_page("gallery-page:3"), Some(3));
+        assert_eq!(parse_gallery_page("gallery-page-indicator"), None);
+        assert_eq!(parse_gallery_page("something-else:3"), None);
+    }
+
+    #[test]
+    fn delete_ids() {
+        let owner = UserId::new(1234);
+        let opts = EmbedOptions { owner: Some(owner), ..Default::default() };
+
+        let response: CreateInteractionResponseMessage = with_components(CreateResponse::new(), Vec::new(), &opts);
+        let json = serde_json::to_value(response).unwrap();
+
+        let custom_id = json["components"][0]["components"][0]["custom_id"].as_str().unwrap();
+        assert_eq!(parse_delete(custom_id), Some(owner));
+        assert_eq!(parse_delete("delete:someone"), None);
+    }
+
+    #[test]
+    fn reveal_ids() {
+        for reason in [HiddenReason::Nsfw, HiddenReason::Spoiler] {
+            assert_eq!(
+                parse_reveal(&format!("{REVEAL_ID_PREFIX}{}", reason.id())),
+                Some(reason)
+            );
+        }
+
+        assert_eq!(parse_reveal("reveal:something"), None);
+    }
 }
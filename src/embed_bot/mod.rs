@@ -1,34 +1,87 @@
+mod attachments;
+mod cache;
 mod config;
+mod config_command;
 mod embed;
+mod settings;
+mod tracking;
+mod urls;
+mod webhooks;
 
 use crate::{
     embed_bot::{
-        config::{EmbedBehaviour, EmbedBehaviours},
-        embed::EmbedOptions,
+        attachments::MediaAttachment,
+        config::{AutoEmbedMode, EmbedBehaviours, NsfwPolicy},
+        config_command::{Action, ConfigCommand},
+        embed::{EmbedOptions, HiddenReason},
+        settings::{Overrides, Settings},
     },
     scraper::{Post, PostScraper},
 };
-pub use config::Config;
+#[cfg(feature = "implicit-auto-embed")]
+use anyhow::Context as _;
+pub use attachments::MediaDownloader;
+pub use cache::PostCache;
+#[cfg(feature = "implicit-auto-embed")]
+use config::AutoEmbedSettings;
+pub use config::{Config, Modules};
+#[cfg(feature = "implicit-auto-embed")]
+use embed::create_response::{CreateResponse, WebhookMessage};
 use itertools::Itertools;
 use serenity::{
     async_trait,
-    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponse},
+    builder::{
+        CreateAllowedMentions, CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse,
+        CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse,
+    },
     client::{Context, EventHandler},
     model::{
         application::{
-            Command, CommandData, CommandDataOption, CommandDataOptionValue, CommandOptionType, CommandType,
-            Interaction,
+            Command, CommandData, CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+            CommandType, ComponentInteraction, Interaction, ResolvedTarget,
         },
-        channel::Message,
         gateway::Ready,
+        guild::PremiumTier,
+        id::{ChannelId, GuildId, UserId},
+    },
+};
+#[cfg(feature = "implicit-auto-embed")]
+use serenity::{
+    builder::{CreateAttachment, EditMessage, ExecuteWebhook},
+    model::{
+        channel::{Message, MessageReference},
+        event::MessageUpdateEvent,
+        id::MessageId,
     },
 };
+pub use settings::SettingsStore;
 use thiserror::Error;
+#[cfg(feature = "implicit-auto-embed")]
+pub use tracking::EmbedTracker;
+#[cfg(feature = "implicit-auto-embed")]
+use tracking::TrackedEmbed;
 use url::Url;
+#[cfg(feature = "implicit-auto-embed")]
+use webhooks::Webhooks;
+
+/// name of the message context menu command
+const EMBED_MESSAGE_COMMAND: &str = "Embed this";
 
 pub struct EmbedBot {
-    apis: Vec<Box<dyn PostScraper + Send + Sync>>,
+    apis: Vec<(&'static str, Box<dyn PostScraper + Send + Sync>)>,
     embed_behaviour: EmbedBehaviours,
+    settings: SettingsStore,
+    cache: Option<PostCache>,
+    media_downloader: Option<MediaDownloader>,
+
+    #[cfg(feature = "implicit-auto-embed")]
+    auto_embed: AutoEmbedSettings,
+
+    #[cfg(feature = "implicit-auto-embed")]
+    webhooks: Webhooks,
+
+    #[cfg(feature = "implicit-auto-embed")]
+    tracker: Option<EmbedTracker>,
 }
 
 #[derive(Debug, Error)]
@@ -42,26 +95,211 @@ pub enum Error {
 
 impl EmbedBot {
     pub fn from_embed_config(config: EmbedBehaviours) -> Self {
-        EmbedBot { apis: Vec::new(), embed_behaviour: config }
+        EmbedBot {
+            apis: Vec::new(),
+            embed_behaviour: config,
+            settings: SettingsStore::default(),
+            cache: None,
+            media_downloader: None,
+
+            #[cfg(feature = "implicit-auto-embed")]
+            auto_embed: AutoEmbedSettings::default(),
+
+            #[cfg(feature = "implicit-auto-embed")]
+            webhooks: Webhooks::default(),
+
+            #[cfg(feature = "implicit-auto-embed")]
+            tracker: None,
+        }
+    }
+
+    pub fn set_post_cache(&mut self, cache: PostCache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn set_settings_store(&mut self, settings: SettingsStore) {
+        self.settings = settings;
+    }
+
+    pub fn set_media_downloader(&mut self, downloader: MediaDownloader) {
+        self.media_downloader = Some(downloader);
+    }
+
+    #[cfg(feature = "implicit-auto-embed")]
+    pub fn set_auto_embed_settings(&mut self, settings: AutoEmbedSettings) {
+        self.auto_embed = settings;
+    }
+
+    #[cfg(feature = "implicit-auto-embed")]
+    pub fn set_embed_tracker(&mut self, tracker: EmbedTracker) {
+        self.tracker = Some(tracker);
+    }
+
+    /// Registers a scraper module, `name` is what guild and channel settings refer to it by
+    pub fn register_api<T: 'static + PostScraper + Send + Sync>(&mut self, name: &'static str, api: T) {
+        self.apis.push((name, Box::new(api)));
+    }
+
+    fn find_api(&self, url: &Url, settings: &Settings) -> Option<&(dyn PostScraper + Send + Sync)> {
+        self.apis
+            .iter()
+            .find(|(name, a)| settings.module_enabled(name) && a.is_suitable(url))
+            .map(|(_, a)| a.as_ref())
+    }
+
+    fn module_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.apis.iter().map(|(name, _)| *name)
+    }
+
+    fn auto_embed_mode(&self) -> AutoEmbedMode {
+        #[cfg(feature = "implicit-auto-embed")]
+        return self.auto_embed.mode;
+
+        #[cfg(not(feature = "implicit-auto-embed"))]
+        AutoEmbedMode::default()
+    }
+
+    fn max_urls_per_message(&self) -> usize {
+        #[cfg(feature = "implicit-auto-embed")]
+        return self.auto_embed.max_urls_per_message;
+
+        #[cfg(not(feature = "implicit-auto-embed"))]
+        config::DEFAULT_MAX_URLS_PER_MESSAGE
     }
 
-    pub fn register_api<T: 'static + PostScraper + Send + Sync>(&mut self, api: T) {
-        self.apis.push(Box::new(api));
+    fn settings(&self, guild_id: Option<GuildId>, channel_id: ChannelId) -> Settings {
+        self.settings
+            .resolve(&self.embed_behaviour, self.auto_embed_mode(), guild_id, channel_id)
     }
 
-    fn find_api(&self, url: &Url) -> Option<&(dyn PostScraper + Send + Sync)> {
-        self.apis.iter().find(|a| a.is_suitable(url)).map(AsRef::as_ref)
+    /// Scrapes `url` as if it was requested outside of any guild
+    pub async fn scrape(&self, url: Url) -> Result<Post, Error> {
+        let settings = Settings::global(&self.embed_behaviour, self.auto_embed_mode());
+        self.scrape_post(url, &settings).await
     }
 
-    async fn scrape_post(&self, mut url: Url) -> Result<Post, Error> {
-        if let Some(api) = self.find_api(&url) {
+    /// The message `/embed` would respond with to `author` outside of any guild, with media linked instead of uploaded
+    pub async fn render(
+        &self,
+        url: Url,
+        author: &str,
+        embed_nsfw: bool,
+        embed_spoiler: bool,
+    ) -> Result<CreateMessage, Error> {
+        let settings = Settings::global(&self.embed_behaviour, self.auto_embed_mode());
+        let post = self.scrape_post(url, &settings).await?;
+
+        let opts = EmbedOptions {
+            embed_nsfw: embed_nsfw || settings.embed_nsfw,
+            embed_spoiler: embed_spoiler || settings.embed_spoiler,
+            style: settings.style,
+            ..Default::default()
+        };
+
+        Ok(embed::embed(&post, author, &opts))
+    }
+
+    async fn scrape_post(&self, mut url: Url, settings: &Settings) -> Result<Post, Error> {
+        if let Some(api) = self.find_api(&url, settings) {
             url.set_fragment(None);
-            let post = api.scrape_post(url).await?;
+
+            let post = match &self.cache {
+                Some(cache) => cache.get_or_scrape(&url, || api.scrape_post(url.clone())).await?,
+                None => api.scrape_post(url).await?,
+            };
+
             Ok(post)
         } else {
             Err(Error::NoScraperAvailable)
         }
     }
+
+    async fn is_nsfw_channel(&self, ctx: &Context, channel_id: ChannelId) -> bool {
+        match channel_id.to_channel(ctx).await {
+            Ok(channel) => channel.guild().is_some_and(|c| c.nsfw),
+            Err(e) => {
+                tracing::warn!("Unable to fetch channel {channel_id}, assuming it is not age-restricted: {e:#}");
+                false
+            },
+        }
+    }
+
+    /// Whether the nsfw policy embeds nsfw posts fully in the given channel regardless of the configured default
+    async fn channel_embeds_nsfw(&self, ctx: &Context, settings: &Settings, channel_id: ChannelId) -> bool {
+        settings.nsfw_policy == NsfwPolicy::ChannelAware && self.is_nsfw_channel(ctx, channel_id).await
+    }
+
+    /// The options for embedding on behalf of `owner` without any explicit requests
+    async fn default_options(
+        &self,
+        ctx: &Context,
+        settings: &Settings,
+        channel_id: ChannelId,
+        owner: UserId,
+    ) -> EmbedOptions<'static> {
+        EmbedOptions {
+            embed_nsfw: settings.embed_nsfw || self.channel_embeds_nsfw(ctx, settings, channel_id).await,
+            embed_spoiler: settings.embed_spoiler,
+            style: settings.style,
+            owner: Some(owner),
+            ..Default::default()
+        }
+    }
+
+    /// Downloads the media of `post` for uploading, unless attachments are disabled or the post is hidden anyway
+    async fn download_attachments(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+        post: &Post,
+        opts: &EmbedOptions<'_>,
+    ) -> Vec<MediaAttachment> {
+        self.download_attachments_beside(ctx, guild_id, post, opts, 0).await
+    }
+
+    /// Like `download_attachments`, for a message that also carries `reserved` bytes of other files
+    async fn download_attachments_beside(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+        post: &Post,
+        opts: &EmbedOptions<'_>,
+        reserved: u64,
+    ) -> Vec<MediaAttachment> {
+        let Some(downloader) = &self.media_downloader else {
+            return Vec::new();
+        };
+
+        // paginated galleries keep replacing their images, which attachments cannot follow
+        if opts.hides(post) || embed::is_paginated(post) {
+            return Vec::new();
+        }
+
+        let upload_limit = self.upload_limit(ctx, guild_id).await;
+        downloader
+            .download_media(post, upload_limit.saturating_sub(reserved))
+            .await
+    }
+
+    /// The total size of the files the bot may upload in a single message to `guild_id`
+    async fn upload_limit(&self, ctx: &Context, guild_id: Option<GuildId>) -> u64 {
+        // the cache is filled from the events of the guilds intent, it may still miss a guild right after startup
+        let cached_tier = guild_id.and_then(|id| ctx.cache.guild(id).map(|guild| guild.premium_tier));
+
+        let tier = match (guild_id, cached_tier) {
+            (_, Some(tier)) => tier,
+            (Some(guild_id), None) => match guild_id.to_partial_guild(ctx).await {
+                Ok(guild) => guild.premium_tier,
+                Err(e) => {
+                    tracing::warn!("Unable to fetch guild {guild_id}, assuming the default upload limit: {e:#}");
+                    PremiumTier::default()
+                },
+            },
+            (None, None) => PremiumTier::default(),
+        };
+
+        attachments::upload_limit(tier)
+    }
 }
 
 macro_rules! server_communication_try {
@@ -83,10 +321,7 @@ macro_rules! interaction_try {
             Err(err) => {
                 server_communication_try!(
                     $command
-                        .create_response(
-                            $ctx,
-                            CreateInteractionResponse::Message(embed::error(format!("Invalid input: {err}"))),
-                        )
+                        .edit_response($ctx, embed::error(format!("Invalid input: {err}")))
                         .await,
                     "Unable to send error response"
                 );
@@ -97,57 +332,699 @@ macro_rules! interaction_try {
     };
 }
 
+impl EmbedBot {
+    async fn turn_gallery_page(&self, ctx: &Context, component: &ComponentInteraction, page: usize) {
+        // the post might have to be scraped again, which can take longer than the 3 seconds discord allows
+        server_communication_try!(component.defer(ctx).await, "Unable to defer response");
+
+        // the message itself is the page state, the post is looked up again by the url of its embed
+        let Some(base) = component.message.embeds.first() else {
+            return;
+        };
+
+        let Some(url) = base.url.as_deref().and_then(|u| Url::parse(u).ok()) else {
+            return;
+        };
+
+        let settings = self.settings(component.guild_id, component.channel_id);
+
+        let post = match self.scrape_post(url.clone(), &settings).await {
+            Ok(post) => post,
+            Err(e) => {
+                tracing::error!("Unable to turn gallery page of {url}: {e}");
+                send_ephemeral_error(ctx, component, format!("Unable to turn page: {e}")).await;
+                return;
+            },
+        };
+
+        let owner = embed::owner(&component.message.components);
+
+        if let Some(response) = embed::gallery_page::<EditInteractionResponse>(&post, base.clone().into(), page, owner)
+        {
+            server_communication_try!(
+                component.edit_response(ctx, response).await,
+                "Unable to turn gallery page"
+            );
+        }
+    }
+
+    async fn reveal(&self, ctx: &Context, component: &ComponentInteraction, reason: HiddenReason) {
+        let public = reason == HiddenReason::Nsfw
+            && self.embed_behaviour.reveal_publicly_in_nsfw_channels
+            && self.is_nsfw_channel(ctx, component.channel_id).await;
+
+        // public reveals replace the hidden embed, private ones are sent only to the clicking user
+        let deferred = if public {
+            component.defer(ctx).await
+        } else {
+            component.defer_ephemeral(ctx).await
+        };
+
+        server_communication_try!(deferred, "Unable to defer response");
+
+        let Some(hidden) = component.message.embeds.first() else {
+            return;
+        };
+
+        let Some(url) = hidden.url.as_deref().and_then(|u| Url::parse(u).ok()) else {
+            return;
+        };
+
+        // the author and their comment are only known from the hidden embed
+        let author = hidden.author.as_ref().map(|a| a.name.as_str()).unwrap_or_default();
+        let comment_title = format!("Comment by {author}");
+        let comment = hidden
+            .fields
+            .iter()
+            .find(|f| f.name == comment_title)
+            .map(|f| f.value.as_str());
+
+        let settings = self.settings(component.guild_id, component.channel_id);
+
+        let post = match self.scrape_post(url.clone(), &settings).await {
+            Ok(post) => post,
+            Err(e) => {
+                tracing::error!("Unable to reveal {url}: {e}");
+
+                if public {
+                    send_ephemeral_error(ctx, component, format!("Unable to reveal post: {e}")).await;
+                } else {
+                    server_communication_try!(
+                        component.edit_response(ctx, embed::error(format!("{e}"))).await,
+                        "Unable to send error response"
+                    );
+                }
+
+                return;
+            },
+        };
+
+        let opts = EmbedOptions {
+            comment,
+            embed_nsfw: true,
+            embed_spoiler: true,
+            style: settings.style,
+            // the private copy is only visible to the clicking user anyway
+            owner: embed::owner(&component.message.components).filter(|_| public),
+            ..Default::default()
+        };
+        let attachments = self.download_attachments(ctx, component.guild_id, &post, &opts).await;
+        let opts = EmbedOptions { attachments: &attachments, ..opts };
+
+        server_communication_try!(
+            component
+                .edit_response(ctx, embed::embed::<EditInteractionResponse>(&post, author, &opts))
+                .await,
+            "Unable to send revealed post"
+        );
+    }
+}
+
+impl EmbedBot {
+    async fn run_config_command(&self, command: &CommandInteraction) -> anyhow::Result<CreateEmbed> {
+        let Some(guild_id) = command.guild_id else {
+            anyhow::bail!("Settings can only be changed within a server");
+        };
+
+        // the default member permissions of the command can be changed by the server, so check again
+        if !command
+            .member
+            .as_ref()
+            .and_then(|m| m.permissions)
+            .is_some_and(|p| p.manage_guild())
+        {
+            anyhow::bail!("Changing settings requires the Manage Server permission");
+        }
+
+        let ConfigCommand { channel, action } = ConfigCommand::parse(&command.data)?;
+
+        match action {
+            Action::Show => {},
+            Action::Set(change) => self.settings.update(guild_id, channel, |o| o.apply(change)).await?,
+            Action::Reset(Some(setting)) => {
+                if !self.settings.update(guild_id, channel, |o| o.reset(&setting)).await? {
+                    anyhow::bail!("Unknown setting {setting}");
+                }
+            },
+            Action::Reset(None) => {
+                self.settings
+                    .update(guild_id, channel, |o| *o = Overrides::default())
+                    .await?
+            },
+        }
+
+        let channel = channel.unwrap_or(command.channel_id);
+
+        Ok(config_command::describe(
+            &self.settings.guild(guild_id),
+            channel,
+            &self.settings(Some(guild_id), channel),
+            self.module_names(),
+        ))
+    }
+
+    async fn configure(&self, ctx: &Context, command: &CommandInteraction) {
+        let response = match self.run_config_command(command).await {
+            Ok(e) => CreateInteractionResponseMessage::new().embed(e),
+            Err(e) => {
+                tracing::info!("Rejected config command: {e:#}");
+                embed::error(format!("{e:#}"))
+            },
+        };
+
+        server_communication_try!(
+            command
+                .create_response(ctx, CreateInteractionResponse::Message(response.ephemeral(true)))
+                .await,
+            "Unable to send config response"
+        );
+    }
+}
+
+impl EmbedBot {
+    /// Replies to the target message of a context menu command with embeds of the urls in it
+    async fn embed_message(&self, ctx: &Context, command: &CommandInteraction) {
+        // scraping can take longer than the 3 seconds discord allows for the initial response
+        server_communication_try!(command.defer_ephemeral(ctx).await, "Unable to defer response");
+
+        let Some(ResolvedTarget::Message(msg)) = command.data.target() else {
+            return;
+        };
+
+        let (mut urls, _) = urls::extract_urls(&msg.content);
+        let settings = self.settings(command.guild_id, command.channel_id);
+
+        let max_urls = self.max_urls_per_message();
+        let truncation_note = if urls.len() > max_urls {
+            format!("\nOnly the first {max_urls} of {} urls were looked at", urls.len())
+        } else {
+            String::new()
+        };
+
+        urls.truncate(max_urls);
+
+        let opts = self
+            .default_options(ctx, &settings, command.channel_id, msg.author.id)
+            .await;
+
+        let mut embedded = 0;
+        let mut errors = Vec::new();
+
+        for url in urls {
+            match self.scrape_post(url.clone(), &settings).await {
+                Ok(post) => {
+                    let attachments = self.download_attachments(ctx, command.guild_id, &post, &opts).await;
+                    let opts = EmbedOptions { attachments: &attachments, ..opts };
+
+                    let reply = embed::embed::<CreateMessage>(&post, msg.author.display_name(), &opts)
+                        .reference_message(msg)
+                        .allowed_mentions(CreateAllowedMentions::new());
+
+                    match msg.channel_id.send_message(ctx, reply).await {
+                        Ok(_) => embedded += 1,
+                        Err(e) => {
+                            tracing::error!("Unable to send embed of {url}: {e:#}");
+                            errors.push(format!("<{url}>: Unable to send embed: {e}"));
+                        },
+                    }
+                },
+                Err(Error::NoScraperAvailable) => {
+                    tracing::info!("Not embedding {url}: no scraper available");
+                },
+                Err(e) => {
+                    tracing::error!("Error while trying to embed {url}: {e}");
+                    errors.push(format!("<{url}>: {e}"));
+                },
+            }
+        }
+
+        let response = if !errors.is_empty() {
+            embed::error(
+                Itertools::intersperse(errors.iter().map(String::as_str), "\n").collect::<String>() + &truncation_note,
+            )
+        } else if embedded == 0 {
+            embed::error(format!(
+                "The message does not contain any supported urls{truncation_note}"
+            ))
+        } else {
+            EditInteractionResponse::new().content(format!(
+                ":white_check_mark: Embedded {embedded} post(s){truncation_note}"
+            ))
+        };
+
+        server_communication_try!(command.edit_response(ctx, response).await, "Unable to send response");
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl EmbedBot {
+    /// Posts `post` in place of `msg`, which is about to be deleted, along with `files` that were attached to it
+    async fn repost(
+        &self,
+        ctx: &Context,
+        msg: &Message,
+        post: &Post,
+        opts: &EmbedOptions<'_>,
+        files: Vec<CreateAttachment>,
+    ) -> anyhow::Result<()> {
+        let author = msg.author.display_name();
+        let mentions = original_mentions(msg);
+
+        // webhooks are unable to reply, so replies are reposted by the bot to keep pointing at their target
+        if self.auto_embed.use_webhooks && msg.referenced_message.is_none() {
+            let nick = msg.member.as_ref().and_then(|m| m.nick.as_deref()).unwrap_or(author);
+            let message = with_files(embed::embed::<WebhookMessage>(post, author, opts), files.clone());
+
+            match self
+                .webhooks
+                .send(
+                    ctx,
+                    msg.channel_id,
+                    nick,
+                    &member_avatar_url(msg),
+                    ExecuteWebhook::from(message).allowed_mentions(mentions.clone()),
+                )
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("Unable to repost via webhook, posting as the bot instead: {e:#}"),
+            }
+        }
+
+        let mut message =
+            with_files(embed::embed::<CreateMessage>(post, author, opts), files).allowed_mentions(mentions);
+
+        if let Some(referenced) = &msg.referenced_message {
+            message = message.reference_message(&**referenced);
+        }
+
+        msg.channel_id.send_message(ctx, message).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+fn with_files<R: CreateResponse>(response: R, files: Vec<CreateAttachment>) -> R {
+    files.into_iter().fold(response, CreateResponse::add_file)
+}
+
+/// Lets a repost of `msg` ping exactly whom `msg` pinged, including the author of the message it replies to
+#[cfg(feature = "implicit-auto-embed")]
+fn original_mentions(msg: &Message) -> CreateAllowedMentions {
+    let replied_user = msg
+        .referenced_message
+        .as_ref()
+        .is_some_and(|referenced| msg.mentions.iter().any(|user| user.id == referenced.author.id));
+
+    CreateAllowedMentions::new()
+        .users(msg.mentions.iter().map(|user| user.id))
+        .roles(msg.mention_roles.iter().copied())
+        .everyone(msg.mention_everyone)
+        .replied_user(replied_user)
+}
+
+/// Downloads the files attached to `msg`, so that they survive its deletion
+#[cfg(feature = "implicit-auto-embed")]
+async fn download_original_attachments(msg: &Message) -> anyhow::Result<Vec<CreateAttachment>> {
+    let mut files = Vec::with_capacity(msg.attachments.len());
+
+    for attachment in &msg.attachments {
+        let data = attachment
+            .download()
+            .await
+            .with_context(|| format!("Unable to download attachment {}", attachment.filename))?;
+
+        files.push(CreateAttachment::bytes(data, attachment.filename.clone()));
+    }
+
+    Ok(files)
+}
+
+/// The avatar the author of `msg` has in the server it was sent in
+#[cfg(feature = "implicit-auto-embed")]
+fn member_avatar_url(msg: &Message) -> String {
+    match (msg.guild_id, msg.member.as_ref().and_then(|m| m.avatar)) {
+        (Some(guild_id), Some(hash)) => format!(
+            "https://cdn.discordapp.com/guilds/{guild_id}/users/{user_id}/avatars/{hash}.{ext}?size=1024",
+            user_id = msg.author.id,
+            ext = if hash.is_animated() { "gif" } else { "webp" },
+        ),
+        _ => msg.author.face(),
+    }
+}
+
+#[cfg(feature = "implicit-auto-embed")]
+impl EmbedBot {
+    /// Brings the embeds of a tracked message in line with its edited content
+    async fn follow_edit(
+        &self,
+        ctx: &Context,
+        tracker: &EmbedTracker,
+        event: &MessageUpdateEvent,
+    ) -> anyhow::Result<()> {
+        // only edits of the content can change the embedded urls
+        let (Some(content), Some(author)) = (&event.content, &event.author) else {
+            return Ok(());
+        };
+
+        let Some(embeds) = tracker.get(event.id) else {
+            return Ok(());
+        };
+
+        let settings = self.settings(event.guild_id, event.channel_id);
+        let (mut urls, _) = urls::extract_urls(content);
+        urls.truncate(self.max_urls_per_message());
+
+        if !settings.auto_embed {
+            urls.clear();
+        }
+
+        if urls.iter().eq(embeds.iter().map(|e| &e.url)) {
+            return Ok(());
+        }
+
+        let opts = self.default_options(ctx, &settings, event.channel_id, author.id).await;
+        let mut previous = embeds.into_iter();
+        let mut tracked = Vec::new();
+
+        // keep going on errors, every embed that still exists has to stay tracked
+        let mut errors = Vec::new();
+
+        for url in urls {
+            let post = match self.scrape_post(url.clone(), &settings).await {
+                Ok(post) => post,
+                Err(e) => {
+                    tracing::info!("Not embedding {url} after edit: {e}");
+                    continue;
+                },
+            };
+
+            let attachments = self.download_attachments(ctx, event.guild_id, &post, &opts).await;
+            let opts = EmbedOptions { attachments: &attachments, ..opts };
+
+            // reuse the messages that are already there before sending new ones
+            match previous.next() {
+                Some(embed) => {
+                    let edited = event
+                        .channel_id
+                        .edit_message(ctx, embed.message_id, embed::embed(&post, author.display_name(), &opts))
+                        .await;
+
+                    match edited {
+                        Ok(_) => tracked.push(TrackedEmbed { url, message_id: embed.message_id }),
+                        Err(e) => {
+                            errors.push(format!("Unable to edit embed {}: {e}", embed.message_id));
+                            tracked.push(embed);
+                        },
+                    }
+                },
+                None => {
+                    let message = embed::embed(&post, author.display_name(), &opts);
+
+                    let sent = event
+                        .channel_id
+                        .send_message(
+                            ctx,
+                            reply_to(message, settings.auto_embed_mode, (event.channel_id, event.id)),
+                        )
+                        .await;
+
+                    match sent {
+                        Ok(sent) => tracked.push(TrackedEmbed { url, message_id: sent.id }),
+                        Err(e) => errors.push(format!("Unable to send embed for {url}: {e}")),
+                    }
+                },
+            }
+        }
+
+        for embed in previous {
+            if let Err(e) = event.channel_id.delete_message(ctx, embed.message_id).await {
+                errors.push(format!("Unable to delete embed {}: {e}", embed.message_id));
+                tracked.push(embed);
+            }
+        }
+
+        if let Err(e) = tracker.track(event.id, tracked).await {
+            errors.push(format!("Unable to track embeds: {e:#}"));
+        }
+
+        anyhow::ensure!(errors.is_empty(), "{}", errors.join("; "));
+        Ok(())
+    }
+
+    /// Deletes the embeds of a deleted message, or forgets about an embed that was deleted itself
+    async fn follow_deletion(&self, ctx: &Context, tracker: &EmbedTracker, channel_id: ChannelId, id: MessageId) {
+        let embeds = match tracker.remove(id).await {
+            Ok(embeds) => embeds,
+            Err(e) => {
+                tracing::error!("Unable to stop tracking deleted message: {e:#}");
+                return;
+            },
+        };
+
+        if embeds.is_empty() {
+            if let Err(e) = tracker.remove_embed(id).await {
+                tracing::error!("Unable to stop tracking deleted embed: {e:#}");
+            }
+        }
+
+        for embed in embeds {
+            if let Err(e) = channel_id.delete_message(ctx, embed.message_id).await {
+                tracing::error!("Unable to delete embed of deleted message: {e:#}");
+            }
+        }
+    }
+}
+
+impl EmbedBot {
+    /// Deletes the message of `component` if it was clicked by the user the embed was made for
+    async fn delete_embed(&self, ctx: &Context, component: &ComponentInteraction, owner: UserId) {
+        if component.user.id != owner {
+            let response = CreateInteractionResponseMessage::new()
+                .content("Only the user who posted the link can delete this embed")
+                .ephemeral(true);
+
+            server_communication_try!(
+                component
+                    .create_response(ctx, CreateInteractionResponse::Message(response))
+                    .await,
+                "Unable to send response"
+            );
+
+            return;
+        }
+
+        server_communication_try!(
+            component
+                .create_response(ctx, CreateInteractionResponse::Acknowledge)
+                .await,
+            "Unable to acknowledge interaction"
+        );
+
+        // also works for webhook messages and without the Manage Messages permission
+        server_communication_try!(component.delete_response(ctx).await, "Unable to delete embed");
+
+        #[cfg(feature = "implicit-auto-embed")]
+        if let Some(tracker) = &self.tracker {
+            if let Err(e) = tracker.remove_embed(component.message.id).await {
+                tracing::error!("Unable to stop tracking deleted embed: {e:#}");
+            }
+        }
+    }
+}
+
+/// Auto-embeds that leave their source message in place reply to it, without pinging its author
+#[cfg(feature = "implicit-auto-embed")]
+fn reply_to(message: CreateMessage, mode: AutoEmbedMode, source: impl Into<MessageReference>) -> CreateMessage {
+    match mode {
+        AutoEmbedMode::Repost => message,
+        AutoEmbedMode::SuppressEmbeds | AutoEmbedMode::Reply => message
+            .reference_message(source)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    }
+}
+
+async fn send_ephemeral_error(ctx: &Context, component: &ComponentInteraction, msg: String) {
+    server_communication_try!(
+        component
+            .create_followup(
+                ctx,
+                CreateInteractionResponseFollowup::new()
+                    .ephemeral(true)
+                    .content(format!(":x: {msg}")),
+            )
+            .await,
+        "Unable to send error response"
+    );
+}
+
 #[async_trait]
 impl EventHandler for EmbedBot {
     #[cfg(feature = "implicit-auto-embed")]
     async fn message(&self, ctx: Context, msg: Message) {
         if !msg.author.bot {
-            let content: Vec<_> = msg.content.lines().collect();
+            let (mut urls, comment) = urls::extract_urls(&msg.content);
+
+            if urls.is_empty() {
+                return;
+            }
 
-            let (url, comment) = match &content[..] {
-                [] => (None, None),
-                [a] => (Url::parse(a).ok(), None),
-                args => {
-                    let (urls, comments): (Vec<_>, Vec<_>) = args
-                        .iter()
-                        .filter(|s| !s.is_empty())
-                        .partition(|a| Url::parse(a).is_ok());
+            let settings = self.settings(msg.guild_id, msg.channel_id);
 
-                    let mut urls = urls.into_iter().map(|u| Url::parse(u).unwrap());
+            if !settings.auto_embed {
+                return;
+            }
 
-                    let comments: String = Itertools::intersperse(comments.into_iter(), "\n").collect();
+            let max_urls = self.max_urls_per_message();
+            let truncated = urls.len() > max_urls;
 
-                    (urls.next(), Some(comments))
-                },
-            };
+            if truncated {
+                tracing::info!("Only embedding the first {max_urls} of {} urls", urls.len());
+                urls.truncate(max_urls);
+            }
 
-            if let Some(url) = url {
-                match self.scrape_post(url.clone()).await {
+            let posts =
+                futures::future::join_all(urls.iter().map(|url| self.scrape_post(url.clone(), &settings))).await;
+
+            // the user message can only be replaced, or its previews suppressed, if nothing it contains would get lost
+            let lossless = !truncated && posts.iter().all(Result::is_ok);
+            let mut replace_msg = lossless && settings.auto_embed_mode == AutoEmbedMode::Repost;
+            let suppress_embeds = lossless && settings.auto_embed_mode == AutoEmbedMode::SuppressEmbeds;
+
+            // files the user attached are uploaded again along with the first repost, within the same upload limit
+            let original_size: u64 = msg.attachments.iter().map(|a| u64::from(a.size)).sum();
+            let mut original_files = Vec::new();
+
+            if replace_msg && original_size > 0 && original_size > self.upload_limit(&ctx, msg.guild_id).await {
+                tracing::info!("Not replacing message: its attachments exceed the upload limit");
+                replace_msg = false;
+            } else if replace_msg {
+                match download_original_attachments(&msg).await {
+                    Ok(files) => original_files = files,
+                    Err(e) => {
+                        tracing::warn!("Not replacing message: {e:#}");
+                        replace_msg = false;
+                    },
+                }
+            }
+
+            let mut comment = Some(comment).filter(|c| replace_msg && !c.is_empty());
+            let base_opts = self
+                .default_options(&ctx, &settings, msg.channel_id, msg.author.id)
+                .await;
+            let mut errors = Vec::new();
+            let mut tracked = Vec::new();
+
+            for (url, post) in urls.iter().zip(posts) {
+                match post {
                     Ok(post) => {
-                        server_communication_try!(
+                        let comment = comment.take();
+                        let opts = EmbedOptions { comment: comment.as_deref(), ..base_opts };
+                        let reserved = if original_files.is_empty() { 0 } else { original_size };
+                        let attachments = self
+                            .download_attachments_beside(&ctx, msg.guild_id, &post, &opts, reserved)
+                            .await;
+                        let opts = EmbedOptions { attachments: &attachments, ..opts };
+
+                        // only a repost replacing the original may pose as its author
+                        let sent = if replace_msg {
+                            let files = std::mem::take(&mut original_files);
+                            self.repost(&ctx, &msg, &post, &opts, files).await
+                        } else {
+                            let message = embed::embed(&post, msg.author.display_name(), &opts);
+
                             msg.channel_id
-                                .send_message(
-                                    &ctx,
-                                    embed::embed(
-                                        &post,
-                                        &msg.author,
-                                        &EmbedOptions { comment: comment.as_deref(), ..Default::default() },
-                                    ),
-                                )
-                                .await,
-                            "Unable to send message"
-                        );
-
-                        server_communication_try!(msg.delete(&ctx).await, "Unable to delete user message");
+                                .send_message(&ctx, reply_to(message, settings.auto_embed_mode, &msg))
+                                .await
+                                .map(|sent| tracked.push(TrackedEmbed { url: url.clone(), message_id: sent.id }))
+                                .map_err(Into::into)
+                        };
+
+                        server_communication_try!(sent, "Unable to send message");
                     },
                     Err(Error::NoScraperAvailable) => {
                         tracing::info!("Not embedding {}: no scraper available", url);
                     },
                     Err(e) => {
                         tracing::error!("Error while trying to embed {}: {}", url, e);
+                        errors.push(format!("<{url}>: {e}"));
                     },
                 }
             }
+
+            if !errors.is_empty() {
+                let summary: String = Itertools::intersperse(errors.iter().map(String::as_str), "\n").collect();
+
+                server_communication_try!(
+                    msg.channel_id.send_message(&ctx, embed::error(summary)).await,
+                    "Unable to send error message"
+                );
+            }
+
+            if replace_msg {
+                server_communication_try!(msg.delete(&ctx).await, "Unable to delete user message");
+            }
+
+            if suppress_embeds {
+                server_communication_try!(
+                    msg.channel_id
+                        .edit_message(&ctx, msg.id, EditMessage::new().suppress_embeds(true))
+                        .await,
+                    "Unable to suppress embeds of user message"
+                );
+            }
+
+            // a replaced message has nothing left to follow
+            if let Some(tracker) = self.tracker.as_ref().filter(|_| !tracked.is_empty()) {
+                if let Err(e) = tracker.track(msg.id, tracked).await {
+                    tracing::error!("Unable to track embeds: {e:#}");
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "implicit-auto-embed")]
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        if let Some(tracker) = &self.tracker {
+            if let Err(e) = self.follow_edit(&ctx, tracker, &event).await {
+                tracing::error!("Unable to update embeds of edited message: {e:#}");
+            }
+        }
+    }
+
+    #[cfg(feature = "implicit-auto-embed")]
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        if let Some(tracker) = &self.tracker {
+            self.follow_deletion(&ctx, tracker, channel_id, deleted_message_id)
+                .await;
+        }
+    }
+
+    #[cfg(feature = "implicit-auto-embed")]
+    async fn message_delete_bulk(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        if let Some(tracker) = &self.tracker {
+            for id in multiple_deleted_messages_ids {
+                self.follow_deletion(&ctx, tracker, channel_id, id).await;
+            }
         }
     }
 
@@ -186,6 +1063,20 @@ impl EventHandler for EmbedBot {
             "Unable to set up commands"
         );
 
+        server_communication_try!(
+            Command::create_global_command(
+                &ctx,
+                CreateCommand::new(EMBED_MESSAGE_COMMAND).kind(CommandType::Message),
+            )
+            .await,
+            "Unable to set up commands"
+        );
+
+        server_communication_try!(
+            Command::create_global_command(&ctx, config_command::create_command(self.module_names())).await,
+            "Unable to set up commands"
+        );
+
         tracing::info!("Logged in");
     }
 
@@ -193,6 +1084,9 @@ impl EventHandler for EmbedBot {
         if let Interaction::Command(command) = &interaction {
             match &command.data {
                 CommandData { name, options, .. } if name == "embed" => {
+                    // scraping can take longer than the 3 seconds discord allows for the initial response
+                    server_communication_try!(command.defer(&ctx).await, "Unable to defer response");
+
                     let url = interaction_try!(
                         &command,
                         &ctx,
@@ -204,34 +1098,63 @@ impl EventHandler for EmbedBot {
                     );
 
                     let comment = interaction_try!(&command, &ctx, parse_option(options, "comment", |x| x.as_str()));
+                    let settings = self.settings(command.guild_id, command.channel_id);
 
-                    let embed_nsfw = select_embed_behaviour(
-                        &self.embed_behaviour.nsfw,
-                        interaction_try!(&command, &ctx, parse_option(options, "embed-nsfw", |x| x.as_bool())),
-                    );
+                    let requested_nsfw =
+                        interaction_try!(&command, &ctx, parse_option(options, "embed-nsfw", |x| x.as_bool()));
+
+                    // an explicit request still wins over the channel, as long as overriding is allowed
+                    let embed_nsfw = match requested_nsfw {
+                        Some(request) if self.embed_behaviour.nsfw.allow_override => request,
+                        _ => settings.embed_nsfw || self.channel_embeds_nsfw(&ctx, &settings, command.channel_id).await,
+                    };
 
                     let embed_spoiler = select_embed_behaviour(
-                        &self.embed_behaviour.spoiler,
+                        self.embed_behaviour.spoiler.allow_override,
+                        settings.embed_spoiler,
                         interaction_try!(&command, &ctx, parse_option(options, "embed-spoiler", |x| x.as_bool())),
                     );
 
-                    let opts = EmbedOptions { comment, embed_nsfw, embed_spoiler };
+                    let opts = EmbedOptions {
+                        comment,
+                        embed_nsfw,
+                        embed_spoiler,
+                        style: settings.style,
+                        owner: Some(command.user.id),
+                        ..Default::default()
+                    };
 
                     match Url::parse(url) {
                         Ok(url) => {
                             let user = &command.user;
 
-                            match self.scrape_post(url.clone()).await {
+                            server_communication_try!(
+                                command.edit_response(&ctx, embed::progress(&url)).await,
+                                "Unable to send progress response"
+                            );
+
+                            match self.scrape_post(url.clone(), &settings).await {
                                 Ok(post) => {
-                                    server_communication_try!(
-                                        command
-                                            .create_response(
-                                                &ctx,
-                                                CreateInteractionResponse::Message(embed::embed(&post, user, &opts)),
-                                            )
-                                            .await,
-                                        "Unable to send response"
-                                    );
+                                    let attachments =
+                                        self.download_attachments(&ctx, command.guild_id, &post, &opts).await;
+                                    let opts = EmbedOptions { attachments: &attachments, ..opts };
+
+                                    if let Err(e) = command
+                                        .edit_response(&ctx, embed::embed(&post, user.display_name(), &opts))
+                                        .await
+                                    {
+                                        tracing::error!("Unable to send response: {e:#}");
+
+                                        // otherwise the progress message would be left standing indefinitely
+                                        server_communication_try!(
+                                            command
+                                                .edit_response(&ctx, embed::error(format!("Unable to send embed: {e}")))
+                                                .await,
+                                            "Unable to send error response"
+                                        );
+
+                                        return;
+                                    }
 
                                     tracing::trace!("embedded '{}': {:?}", url, post);
                                 },
@@ -240,12 +1163,7 @@ impl EventHandler for EmbedBot {
                                     tracing::error!("error: {msg}");
 
                                     server_communication_try!(
-                                        command
-                                            .create_response(
-                                                &ctx,
-                                                CreateInteractionResponse::Message(embed::error(msg))
-                                            )
-                                            .await,
+                                        command.edit_response(&ctx, embed::error(msg)).await,
                                         "Unable to send error response"
                                     );
                                 },
@@ -254,21 +1172,32 @@ impl EventHandler for EmbedBot {
                         Err(_) => {
                             server_communication_try!(
                                 command
-                                    .create_response(
-                                        &ctx,
-                                        CreateInteractionResponse::Message({
-                                            embed::error(format!("Could not parse url: {url}"))
-                                        }),
-                                    )
+                                    .edit_response(&ctx, embed::error(format!("Could not parse url: {url}")))
                                     .await,
                                 "Unable to send error response"
                             );
                         },
                     }
                 },
+                CommandData { name, .. } if name == EMBED_MESSAGE_COMMAND => {
+                    self.embed_message(&ctx, command).await;
+                },
+                CommandData { name, .. } if name == config_command::NAME => {
+                    self.configure(&ctx, command).await;
+                },
                 _ => (),
             }
         }
+
+        if let Interaction::Component(component) = &interaction {
+            if let Some(page) = embed::parse_gallery_page(&component.data.custom_id) {
+                self.turn_gallery_page(&ctx, component, page).await;
+            } else if let Some(reason) = embed::parse_reveal(&component.data.custom_id) {
+                self.reveal(&ctx, component, reason).await;
+            } else if let Some(owner) = embed::parse_delete(&component.data.custom_id) {
+                self.delete_embed(&ctx, component, owner).await;
+            }
+        }
     }
 }
 
@@ -292,9 +1221,9 @@ where
     }
 }
 
-fn select_embed_behaviour(behav: &EmbedBehaviour, requested: Option<bool>) -> bool {
+fn select_embed_behaviour(allow_override: bool, default: bool, requested: Option<bool>) -> bool {
     match requested {
-        Some(request) if behav.allow_override => request,
-        _ => behav.default,
+        Some(request) if allow_override => request,
+        _ => default,
     }
 }
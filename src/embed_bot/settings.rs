@@ -0,0 +1,343 @@
+use crate::embed_bot::config::{AutoEmbedMode, EmbedBehaviours, EmbedStyle, NsfwPolicy};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Settings of a guild or channel that deviate from the global config, unset fields fall through
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Overrides {
+    pub embed_nsfw: Option<bool>,
+    pub embed_spoiler: Option<bool>,
+    pub nsfw_policy: Option<NsfwPolicy>,
+    pub auto_embed: Option<bool>,
+    pub style: Option<EmbedStyle>,
+
+    /// what auto-embed does with the message containing the urls
+    pub auto_embed_mode: Option<AutoEmbedMode>,
+
+    /// scraper modules by name, modules that are not listed stay enabled
+    pub modules: BTreeMap<String, bool>,
+}
+
+impl Overrides {
+    /// Fills the fields that are unset in `self` from `fallback`
+    fn or(self, fallback: &Overrides) -> Overrides {
+        let mut modules = fallback.modules.clone();
+        modules.extend(self.modules);
+
+        Overrides {
+            embed_nsfw: self.embed_nsfw.or(fallback.embed_nsfw),
+            embed_spoiler: self.embed_spoiler.or(fallback.embed_spoiler),
+            nsfw_policy: self.nsfw_policy.or(fallback.nsfw_policy),
+            auto_embed: self.auto_embed.or(fallback.auto_embed),
+            style: self.style.or(fallback.style),
+            auto_embed_mode: self.auto_embed_mode.or(fallback.auto_embed_mode),
+            modules,
+        }
+    }
+
+    /// Sets everything that is set in `change`
+    pub fn apply(&mut self, change: Overrides) {
+        *self = change.or(self);
+    }
+
+    /// Unsets the setting named `name` (one of `SETTING_NAMES`), returns whether there is such a setting
+    pub fn reset(&mut self, name: &str) -> bool {
+        match name {
+            "embed-nsfw" => self.embed_nsfw = None,
+            "embed-spoiler" => self.embed_spoiler = None,
+            "nsfw-policy" => self.nsfw_policy = None,
+            "auto-embed" => self.auto_embed = None,
+            "style" => self.style = None,
+            "auto-embed-mode" => self.auto_embed_mode = None,
+            "modules" => self.modules.clear(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Overrides::default()
+    }
+}
+
+/// The names of the settings as used in the settings file and by `Overrides::reset`
+pub const SETTING_NAMES: &[&str] = &[
+    "embed-nsfw",
+    "embed-spoiler",
+    "nsfw-policy",
+    "auto-embed",
+    "style",
+    "auto-embed-mode",
+    "modules",
+];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GuildSettings {
+    pub guild: Overrides,
+    pub channels: HashMap<ChannelId, Overrides>,
+}
+
+/// The behaviour within a single channel, after applying all overrides to the global config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub embed_nsfw: bool,
+    pub embed_spoiler: bool,
+    pub nsfw_policy: NsfwPolicy,
+    pub auto_embed: bool,
+    pub style: EmbedStyle,
+    pub auto_embed_mode: AutoEmbedMode,
+    modules: BTreeMap<String, bool>,
+}
+
+impl Settings {
+    /// Applies `overrides` on top of `defaults` and `auto_embed_mode`
+    fn new(defaults: &EmbedBehaviours, auto_embed_mode: AutoEmbedMode, overrides: Overrides) -> Self {
+        Self {
+            embed_nsfw: overrides.embed_nsfw.unwrap_or(defaults.nsfw.default),
+            embed_spoiler: overrides.embed_spoiler.unwrap_or(defaults.spoiler.default),
+            nsfw_policy: overrides.nsfw_policy.unwrap_or(defaults.nsfw_policy),
+            auto_embed: overrides.auto_embed.unwrap_or(true),
+            style: overrides.style.unwrap_or(defaults.style),
+            auto_embed_mode: overrides.auto_embed_mode.unwrap_or(auto_embed_mode),
+            modules: overrides.modules,
+        }
+    }
+
+    /// The settings outside of any guild
+    pub fn global(defaults: &EmbedBehaviours, auto_embed_mode: AutoEmbedMode) -> Self {
+        Self::new(defaults, auto_embed_mode, Overrides::default())
+    }
+
+    pub fn module_enabled(&self, name: &str) -> bool {
+        self.modules.get(name).copied().unwrap_or(true)
+    }
+}
+
+#[derive(Default)]
+pub struct SettingsStore {
+    /// changes are only kept in memory if not set
+    path: Option<PathBuf>,
+    guilds: Mutex<HashMap<GuildId, GuildSettings>>,
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl SettingsStore {
+    pub async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let guilds = match tokio::fs::read(&path).await {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("Unable to parse settings at {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Unable to read settings at {}", path.display())),
+        };
+
+        Ok(Self { path: Some(path), ..Self::with_guilds(guilds) })
+    }
+
+    fn with_guilds(guilds: HashMap<GuildId, GuildSettings>) -> Self {
+        Self { guilds: Mutex::new(guilds), ..Default::default() }
+    }
+
+    pub fn guild(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds.lock().unwrap().get(&guild_id).cloned().unwrap_or_default()
+    }
+
+    /// The settings for `channel_id`, channel overrides take precedence over guild overrides which take
+    /// precedence over `defaults` and `auto_embed_mode`
+    pub fn resolve(
+        &self,
+        defaults: &EmbedBehaviours,
+        auto_embed_mode: AutoEmbedMode,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+    ) -> Settings {
+        let overrides = match guild_id {
+            Some(guild_id) => {
+                let guilds = self.guilds.lock().unwrap();
+
+                match guilds.get(&guild_id) {
+                    Some(guild) => guild
+                        .channels
+                        .get(&channel_id)
+                        .cloned()
+                        .unwrap_or_default()
+                        .or(&guild.guild),
+                    None => Overrides::default(),
+                }
+            },
+            None => Overrides::default(),
+        };
+
+        Settings::new(defaults, auto_embed_mode, overrides)
+    }
+
+    /// Changes the overrides of a whole guild, or of a single channel in it if `channel_id` is given
+    pub async fn update<T>(
+        &self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        f: impl FnOnce(&mut Overrides) -> T,
+    ) -> anyhow::Result<T> {
+        let res = {
+            let mut guilds = self.guilds.lock().unwrap();
+            let guild = guilds.entry(guild_id).or_default();
+
+            let res = match channel_id {
+                Some(channel_id) => {
+                    let channel = guild.channels.entry(channel_id).or_default();
+                    let res = f(channel);
+
+                    if channel.is_empty() {
+                        guild.channels.remove(&channel_id);
+                    }
+
+                    res
+                },
+                None => f(&mut guild.guild),
+            };
+
+            if guild.guild.is_empty() && guild.channels.is_empty() {
+                guilds.remove(&guild_id);
+            }
+
+            res
+        };
+
+        self.persist().await?;
+        Ok(res)
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let _guard = self.persist_lock.lock().await;
+
+        let content = serde_json::to_vec_pretty(&*self.guilds.lock().unwrap())?;
+
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed_bot::config::EmbedBehaviour;
+
+    fn defaults() -> EmbedBehaviours {
+        EmbedBehaviours {
+            nsfw: EmbedBehaviour { default: false, allow_override: false },
+            spoiler: EmbedBehaviour { default: true, allow_override: true },
+            nsfw_policy: NsfwPolicy::Static,
+            reveal_publicly_in_nsfw_channels: false,
+            style: EmbedStyle::Full,
+        }
+    }
+
+    #[test]
+    fn channel_overrides_guild_overrides_defaults() {
+        let (guild, channel, other_channel) = (GuildId::new(1), ChannelId::new(2), ChannelId::new(3));
+
+        let settings = GuildSettings {
+            guild: Overrides {
+                embed_nsfw: Some(true),
+                style: Some(EmbedStyle::Compact),
+                auto_embed_mode: Some(AutoEmbedMode::Reply),
+                modules: BTreeMap::from([("reddit".to_owned(), false)]),
+                ..Default::default()
+            },
+            channels: HashMap::from([(
+                channel,
+                Overrides {
+                    embed_nsfw: Some(false),
+                    modules: BTreeMap::from([("reddit".to_owned(), true)]),
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        let store = SettingsStore::with_guilds(HashMap::from([(guild, settings)]));
+
+        let settings = store.resolve(&defaults(), AutoEmbedMode::Repost, Some(guild), channel);
+        assert!(!settings.embed_nsfw);
+        assert!(settings.embed_spoiler);
+        assert_eq!(settings.style, EmbedStyle::Compact);
+        assert!(settings.module_enabled("reddit"));
+
+        let settings = store.resolve(&defaults(), AutoEmbedMode::Repost, Some(guild), other_channel);
+        assert!(settings.embed_nsfw);
+        assert_eq!(settings.auto_embed_mode, AutoEmbedMode::Reply);
+        assert!(!settings.module_enabled("reddit"));
+        assert!(settings.module_enabled("twitter"));
+
+        let settings = store.resolve(&defaults(), AutoEmbedMode::Repost, None, channel);
+        assert_eq!(settings.style, EmbedStyle::Full);
+        assert!(settings.auto_embed);
+        assert_eq!(settings.auto_embed_mode, AutoEmbedMode::Repost);
+    }
+
+    #[tokio::test]
+    async fn updates() {
+        let store = SettingsStore::default();
+        let (guild, channel) = (GuildId::new(1), ChannelId::new(2));
+
+        store
+            .update(guild, Some(channel), |o| {
+                o.apply(Overrides { auto_embed: Some(false), ..Default::default() });
+                o.apply(Overrides { style: Some(EmbedStyle::Compact), ..Default::default() });
+            })
+            .await
+            .unwrap();
+
+        let settings = store.resolve(&defaults(), AutoEmbedMode::Repost, Some(guild), channel);
+        assert!(!settings.auto_embed);
+        assert_eq!(settings.style, EmbedStyle::Compact);
+
+        assert!(store.update(guild, Some(channel), |o| o.reset("style")).await.unwrap());
+        assert!(!store.update(guild, Some(channel), |o| o.reset("colour")).await.unwrap());
+        assert_eq!(
+            store
+                .resolve(&defaults(), AutoEmbedMode::Repost, Some(guild), channel)
+                .style,
+            EmbedStyle::Full
+        );
+
+        // overrides that no longer change anything are dropped
+        store
+            .update(guild, Some(channel), |o| o.reset("auto-embed"))
+            .await
+            .unwrap();
+        assert!(store.guilds.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let mut settings = GuildSettings::default();
+        settings.guild.nsfw_policy = Some(NsfwPolicy::ChannelAware);
+        settings.channels.insert(
+            ChannelId::new(2),
+            Overrides { auto_embed: Some(false), ..Default::default() },
+        );
+
+        let guilds = HashMap::from([(GuildId::new(1), settings)]);
+        let json = serde_json::to_string(&guilds).unwrap();
+        let parsed: HashMap<GuildId, GuildSettings> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed[&GuildId::new(1)].guild, guilds[&GuildId::new(1)].guild);
+        assert_eq!(parsed[&GuildId::new(1)].channels, guilds[&GuildId::new(1)].channels);
+    }
+}
@@ -0,0 +1,190 @@
+#![cfg(feature = "implicit-auto-embed")]
+
+use crate::embed_bot::config::TrackingSettings;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::MessageId;
+use std::{collections::HashMap, io::ErrorKind, sync::Mutex, time::SystemTime};
+use url::Url;
+
+/// A message the bot sent to embed a url of a user message
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackedEmbed {
+    pub url: Url,
+    pub message_id: MessageId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Source {
+    embeds: Vec<TrackedEmbed>,
+    created_at: SystemTime,
+}
+
+/// Remembers which embeds were sent for which user messages, so they can follow edits and deletions
+pub struct EmbedTracker {
+    settings: TrackingSettings,
+    sources: Mutex<HashMap<MessageId, Source>>,
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl EmbedTracker {
+    pub async fn load(settings: TrackingSettings) -> anyhow::Result<Self> {
+        let mut sources = HashMap::new();
+
+        if let Some(path) = &settings.path {
+            match tokio::fs::read(path).await {
+                Ok(content) => {
+                    sources = serde_json::from_slice(&content)
+                        .with_context(|| format!("Unable to parse tracked messages at {}", path.display()))?;
+                },
+                Err(e) if e.kind() == ErrorKind::NotFound => {},
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Unable to read tracked messages at {}", path.display()));
+                },
+            }
+        }
+
+        Ok(Self::with_sources(settings, sources))
+    }
+
+    fn with_sources(settings: TrackingSettings, sources: HashMap<MessageId, Source>) -> Self {
+        Self {
+            settings,
+            sources: Mutex::new(sources),
+            persist_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self, source: MessageId) -> Option<Vec<TrackedEmbed>> {
+        self.sources.lock().unwrap().get(&source).map(|s| s.embeds.clone())
+    }
+
+    /// Replaces the embeds tracked for `source`, an empty list stops tracking it
+    pub async fn track(&self, source: MessageId, embeds: Vec<TrackedEmbed>) -> anyhow::Result<()> {
+        {
+            let mut sources = self.sources.lock().unwrap();
+
+            if embeds.is_empty() {
+                sources.remove(&source);
+            } else {
+                let created_at = sources.get(&source).map_or_else(SystemTime::now, |s| s.created_at);
+                sources.insert(source, Source { embeds, created_at });
+                self.evict(&mut sources);
+            }
+        }
+
+        self.persist().await
+    }
+
+    /// Stops tracking `source`, returning the embeds that were sent for it
+    pub async fn remove(&self, source: MessageId) -> anyhow::Result<Vec<TrackedEmbed>> {
+        let removed = self.sources.lock().unwrap().remove(&source);
+
+        match removed {
+            Some(source) => {
+                self.persist().await?;
+                Ok(source.embeds)
+            },
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Forgets the embed sent as `message_id`, after it was deleted on its own
+    pub async fn remove_embed(&self, message_id: MessageId) -> anyhow::Result<()> {
+        let removed = {
+            let mut sources = self.sources.lock().unwrap();
+            let mut removed = false;
+
+            sources.retain(|_, source| {
+                let len = source.embeds.len();
+                source.embeds.retain(|e| e.message_id != message_id);
+                removed |= source.embeds.len() != len;
+
+                !source.embeds.is_empty()
+            });
+
+            removed
+        };
+
+        if removed {
+            self.persist().await?;
+        }
+
+        Ok(())
+    }
+
+    fn evict(&self, sources: &mut HashMap<MessageId, Source>) {
+        while sources.len() > self.settings.max_entries {
+            let oldest = sources
+                .iter()
+                .min_by_key(|(_, source)| source.created_at)
+                .map(|(id, _)| *id);
+
+            match oldest {
+                Some(id) => sources.remove(&id),
+                None => break,
+            };
+        }
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.settings.path else {
+            return Ok(());
+        };
+
+        let _guard = self.persist_lock.lock().await;
+
+        let content = serde_json::to_vec(&*self.sources.lock().unwrap())?;
+
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> TrackingSettings {
+        TrackingSettings { max_entries: 2, path: None }
+    }
+
+    fn embed(message_id: u64) -> TrackedEmbed {
+        TrackedEmbed {
+            url: Url::parse("https://reddit.com/r/a").unwrap(),
+            message_id: MessageId::new(message_id),
+        }
+    }
+
+    #[tokio::test]
+    async fn track_and_remove() {
+        let tracker = EmbedTracker::with_sources(settings(), HashMap::new());
+        let source = MessageId::new(1);
+
+        tracker.track(source, vec![embed(10), embed(11)]).await.unwrap();
+        assert_eq!(tracker.get(source), Some(vec![embed(10), embed(11)]));
+
+        tracker.remove_embed(MessageId::new(10)).await.unwrap();
+        assert_eq!(tracker.get(source), Some(vec![embed(11)]));
+
+        assert_eq!(tracker.remove(source).await.unwrap(), vec![embed(11)]);
+        assert_eq!(tracker.get(source), None);
+    }
+
+    #[tokio::test]
+    async fn oldest_sources_are_evicted() {
+        let source = |id: u64, age_secs: u64| {
+            let created_at = SystemTime::now() - std::time::Duration::from_secs(age_secs);
+            (MessageId::new(id), Source { embeds: vec![embed(id + 10)], created_at })
+        };
+
+        let tracker = EmbedTracker::with_sources(settings(), HashMap::from([source(1, 20), source(2, 10)]));
+        tracker.track(MessageId::new(3), vec![embed(13)]).await.unwrap();
+
+        assert_eq!(tracker.get(MessageId::new(1)), None);
+        assert!(tracker.get(MessageId::new(3)).is_some());
+    }
+}
@@ -0,0 +1,85 @@
+use itertools::Itertools;
+use url::Url;
+
+fn parse_url(word: &str) -> Option<Url> {
+    // <url> is discord syntax for suppressing the native embed, so treat it just like a plain url
+    let word = word.strip_prefix('<').and_then(|w| w.strip_suffix('>')).unwrap_or(word);
+
+    Url::parse(word)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Splits a message into the urls it contains (in order of appearance and without duplicates)
+/// and the remaining text, which is treated as a comment on the urls
+pub fn extract_urls(content: &str) -> (Vec<Url>, String) {
+    let mut urls: Vec<Url> = Vec::new();
+
+    let comment_lines = content.lines().filter_map(|line| {
+        let mut comment_words = Vec::new();
+
+        for word in line.split_whitespace() {
+            match parse_url(word) {
+                Some(url) => {
+                    if !urls.contains(&url) {
+                        urls.push(url);
+                    }
+                },
+                None => comment_words.push(word),
+            }
+        }
+
+        if comment_words.is_empty() {
+            None
+        } else {
+            Some(comment_words.join(" "))
+        }
+    });
+
+    let comment = Itertools::intersperse(comment_lines, "\n".to_owned()).collect();
+
+    (urls, comment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_urls;
+    use url::Url;
+
+    #[test]
+    fn urls_on_own_lines() {
+        let (urls, comment) = extract_urls("look at this\nhttps://reddit.com/r/a\n\nhttps://9gag.com/gag/b");
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://reddit.com/r/a").unwrap(),
+                Url::parse("https://9gag.com/gag/b").unwrap()
+            ]
+        );
+        assert_eq!(comment, "look at this");
+    }
+
+    #[test]
+    fn inline_urls() {
+        let (urls, comment) =
+            extract_urls("first <https://x.com/a/status/1> then https://reddit.com/r/a and https://x.com/a/status/1");
+
+        assert_eq!(
+            urls,
+            vec![
+                Url::parse("https://x.com/a/status/1").unwrap(),
+                Url::parse("https://reddit.com/r/a").unwrap()
+            ]
+        );
+        assert_eq!(comment, "first then and");
+    }
+
+    #[test]
+    fn no_urls() {
+        let (urls, comment) = extract_urls("mailto:someone and some text");
+
+        assert!(urls.is_empty());
+        assert_eq!(comment, "mailto:someone and some text");
+    }
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "implicit-auto-embed")]
+
+use anyhow::Context as _;
+use serenity::{
+    builder::{CreateWebhook, ExecuteWebhook},
+    client::Context,
+    model::{channel::Channel, id::ChannelId, webhook::Webhook},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+const WEBHOOK_NAME: &str = "embedbot";
+
+#[derive(Clone)]
+struct Target {
+    webhook: Webhook,
+
+    /// set if the messages go into a thread of the channel the webhook belongs to
+    thread_id: Option<ChannelId>,
+}
+
+/// The webhooks used to post on behalf of users, by the channel they post to
+#[derive(Default)]
+pub struct Webhooks {
+    targets: Mutex<HashMap<ChannelId, Target>>,
+}
+
+impl Webhooks {
+    async fn find_or_create(ctx: &Context, channel_id: ChannelId) -> anyhow::Result<Target> {
+        let Channel::Guild(channel) = channel_id.to_channel(ctx).await? else {
+            anyhow::bail!("Webhooks are only available in server channels");
+        };
+
+        // threads have no webhooks of their own, the webhook of the parent channel is told to post into them
+        let (parent_id, thread_id) = match channel.thread_metadata {
+            Some(_) => (
+                channel.parent_id.context("Thread without parent channel")?,
+                Some(channel.id),
+            ),
+            None => (channel.id, None),
+        };
+
+        // only webhooks created by the bot itself are allowed to carry its buttons
+        let application_id = ctx.http.application_id();
+        let existing = parent_id
+            .webhooks(ctx)
+            .await?
+            .into_iter()
+            .find(|w| w.token.is_some() && w.application_id.is_some() && w.application_id == application_id);
+
+        let webhook = match existing {
+            Some(webhook) => webhook,
+            None => parent_id.create_webhook(ctx, CreateWebhook::new(WEBHOOK_NAME)).await?,
+        };
+
+        Ok(Target { webhook, thread_id })
+    }
+
+    /// Sends `message` to `channel_id` under the name and avatar of someone else
+    pub async fn send(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        username: &str,
+        avatar_url: &str,
+        message: ExecuteWebhook,
+    ) -> anyhow::Result<()> {
+        let cached = self.targets.lock().unwrap().get(&channel_id).cloned();
+
+        let target = match cached {
+            Some(target) => target,
+            None => {
+                let target = Self::find_or_create(ctx, channel_id).await?;
+                self.targets.lock().unwrap().insert(channel_id, target.clone());
+                target
+            },
+        };
+
+        let mut message = message.username(username).avatar_url(avatar_url).with_components(true);
+
+        if let Some(thread_id) = target.thread_id {
+            message = message.in_thread(thread_id);
+        }
+
+        if let Err(e) = target.webhook.execute(ctx, false, message).await {
+            // most likely the webhook was deleted in the meantime, so look it up again next time
+            self.targets.lock().unwrap().remove(&channel_id);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
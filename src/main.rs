@@ -2,34 +2,67 @@ mod embed_bot;
 mod scraper;
 
 use anyhow::Context;
-use clap::Parser;
-use embed_bot::{Config, EmbedBot};
+use clap::{Parser, Subcommand};
+#[cfg(feature = "implicit-auto-embed")]
+use embed_bot::EmbedTracker;
+use embed_bot::{Config, EmbedBot, MediaDownloader, Modules, PostCache, SettingsStore};
+use scraper::http::HttpClient;
 use serenity::{prelude::GatewayIntents, Client};
 use std::{
     path::{Path, PathBuf},
     process::ExitCode,
 };
 use tokio::select;
+use url::Url;
 
 #[cfg(feature = "implicit-auto-embed")]
 fn get_gateway_intents() -> GatewayIntents {
-    GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT
+    GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT
 }
 
 #[cfg(not(feature = "implicit-auto-embed"))]
 fn get_gateway_intents() -> GatewayIntents {
-    GatewayIntents::empty()
+    GatewayIntents::GUILDS
 }
 
 #[derive(Parser)]
 struct Opts {
     #[clap(long, default_value = "/etc/embedbot.toml")]
     config_path: PathBuf,
+
+    /// defaults to `run`
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect to discord and embed posts
+    Run,
+
+    /// Print the post scraped from a url as json, without connecting to discord
+    Scrape { url: Url },
+
+    /// Print the message the bot would respond with to `/embed <url>` as json, without connecting to discord
+    Render {
+        url: Url,
+
+        /// the name of the user the post is embedded for
+        #[clap(long, default_value = "embedbot")]
+        author: String,
+
+        #[clap(long)]
+        embed_nsfw: bool,
+
+        #[clap(long)]
+        embed_spoiler: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    tracing_subscriber::fmt::init();
+    // stdout is reserved for the output of the debugging commands
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
 
     let opts = Opts::parse();
 
@@ -50,30 +83,78 @@ async fn main() -> ExitCode {
 async fn entrypoint(opts: Opts) -> anyhow::Result<()> {
     let config = load_config(&opts.config_path).await.context("Unable to load config")?;
 
+    match opts.command.unwrap_or(Command::Run) {
+        Command::Run => run(config).await,
+        Command::Scrape { url } => {
+            let post = scraper_only_bot(config)?.scrape(url).await?;
+            println!("{}", serde_json::to_string_pretty(&post)?);
+            Ok(())
+        },
+        Command::Render { url, author, embed_nsfw, embed_spoiler } => {
+            let message = scraper_only_bot(config)?
+                .render(url, &author, embed_nsfw, embed_spoiler)
+                .await?;
+
+            println!("{}", serde_json::to_string_pretty(&message)?);
+            Ok(())
+        },
+    }
+}
+
+/// A bot that only scrapes, without any of the state the running bot keeps
+fn scraper_only_bot(config: Config) -> anyhow::Result<EmbedBot> {
+    let http = HttpClient::new()?;
+    let mut e = EmbedBot::from_embed_config(config.embed_behaviour);
+
+    #[cfg(feature = "implicit-auto-embed")]
+    e.set_auto_embed_settings(config.auto_embed);
+
+    register_modules(&mut e, config.modules, &http)?;
+
+    Ok(e)
+}
+
+async fn run(config: Config) -> anyhow::Result<()> {
+    let discord_token = config
+        .discord_token
+        .context("discord-token has to be set to run the bot")?;
+
     let embed_bot = {
+        let http = HttpClient::new()?;
         let mut e = EmbedBot::from_embed_config(config.embed_behaviour);
 
-        if let Some(modules) = config.modules {
-            #[cfg(feature = "reddit")]
-            if let Some(settings) = modules.reddit {
-                e.register_api(scraper::reddit::Api::from_settings(settings));
-            }
-
-            #[cfg(feature = "ninegag")]
-            if let Some(settings) = modules.ninegag {
-                e.register_api(scraper::ninegag::Api::from_settings(settings));
-            }
-
-            #[cfg(feature = "twitter")]
-            if let Some(settings) = modules.twitter {
-                e.register_api(scraper::twitter::Api::from_settings(settings));
-            }
+        #[cfg(feature = "implicit-auto-embed")]
+        e.set_auto_embed_settings(config.auto_embed);
+
+        #[cfg(feature = "implicit-auto-embed")]
+        if let Some(settings) = config.tracking {
+            e.set_embed_tracker(
+                EmbedTracker::load(settings)
+                    .await
+                    .context("Unable to load tracked messages")?,
+            );
+        }
+
+        if let Some(settings) = config.cache {
+            e.set_post_cache(PostCache::load(settings).await.context("Unable to load post cache")?);
         }
 
+        if let Some(path) = config.settings_path {
+            e.set_settings_store(SettingsStore::load(path).await.context("Unable to load settings")?);
+        }
+
+        if let Some(settings) = config.attachments {
+            e.set_media_downloader(
+                MediaDownloader::new(settings, &http).context("Unable to set up attachment downloads")?,
+            );
+        }
+
+        register_modules(&mut e, config.modules, &http)?;
+
         e
     };
 
-    let mut client = Client::builder(&config.discord_token, get_gateway_intents())
+    let mut client = Client::builder(&discord_token, get_gateway_intents())
         .event_handler(embed_bot)
         .await
         .expect("could not create client");
@@ -83,6 +164,72 @@ async fn entrypoint(opts: Opts) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Registers the scraper modules that are configured in `modules`
+fn register_modules(e: &mut EmbedBot, modules: Option<Modules>, http: &HttpClient) -> anyhow::Result<()> {
+    let Some(modules) = modules else {
+        return Ok(());
+    };
+
+    #[cfg(feature = "reddit")]
+    if let Some(settings) = modules.reddit {
+        e.register_api(
+            "reddit",
+            scraper::reddit::Api::from_settings(settings, http).context("Unable to set up reddit module")?,
+        );
+    }
+
+    #[cfg(feature = "ninegag")]
+    if let Some(settings) = modules.ninegag {
+        e.register_api(
+            "ninegag",
+            scraper::ninegag::Api::from_settings(settings, http).context("Unable to set up ninegag module")?,
+        );
+    }
+
+    #[cfg(feature = "twitter")]
+    if let Some(settings) = modules.twitter {
+        e.register_api(
+            "twitter",
+            scraper::twitter::Api::from_settings(settings, http).context("Unable to set up twitter module")?,
+        );
+    }
+
+    #[cfg(feature = "bluesky")]
+    if let Some(settings) = modules.bluesky {
+        e.register_api(
+            "bluesky",
+            scraper::bluesky::Api::from_settings(settings, http).context("Unable to set up bluesky module")?,
+        );
+    }
+
+    #[cfg(feature = "mastodon")]
+    if let Some(settings) = modules.mastodon {
+        e.register_api(
+            "mastodon",
+            scraper::mastodon::Api::from_settings(settings, http).context("Unable to set up mastodon module")?,
+        );
+    }
+
+    #[cfg(feature = "oembed")]
+    if let Some(settings) = modules.oembed {
+        e.register_api(
+            "oembed",
+            scraper::oembed::Api::from_settings(settings, http).context("Unable to set up oembed module")?,
+        );
+    }
+
+    // generic fallback, has to be registered last so that dedicated scrapers take precedence
+    #[cfg(feature = "opengraph")]
+    if let Some(settings) = modules.opengraph {
+        e.register_api(
+            "opengraph",
+            scraper::opengraph::Api::from_settings(settings, http).context("Unable to set up opengraph module")?,
+        );
+    }
+
+    Ok(())
+}
+
 async fn load_config(path: &Path) -> anyhow::Result<Config> {
     let settings_str = tokio::fs::read_to_string(path)
         .await
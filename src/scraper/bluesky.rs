@@ -0,0 +1,477 @@
+#![cfg(feature = "bluesky")]
+
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    GalleryImage, Post, PostCommonData, PostScraper, PostSpecializedData,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use url::Url;
+
+/// Labels (see <https://docs.bsky.app/docs/advanced-guides/moderation>) that hide content by default
+const NSFW_LABELS: [&str; 5] = ["porn", "sexual", "nudity", "graphic-media", "gore"];
+
+#[derive(Debug, Deserialize)]
+struct ThreadResponse {
+    thread: Thread,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum Thread {
+    #[serde(rename = "app.bsky.feed.defs#threadViewPost")]
+    Post { post: Box<PostView> },
+
+    #[serde(other)]
+    Unavailable,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Author {
+    handle: String,
+    display_name: Option<String>,
+}
+
+impl Author {
+    fn fmt_name(&self) -> String {
+        match &self.display_name {
+            Some(name) if !name.is_empty() => format!("{name} (@{handle})", handle = self.handle),
+            _ => format!("@{handle}", handle = self.handle),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    val: String,
+}
+
+fn has_nsfw_label(labels: &[Label]) -> bool {
+    labels.iter().any(|l| NSFW_LABELS.contains(&l.val.as_str()))
+}
+
+#[derive(Debug, Deserialize)]
+struct PostRecord {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostView {
+    author: Author,
+    record: PostRecord,
+    embed: Option<Embed>,
+
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageView {
+    fullsize: Url,
+
+    #[serde(default)]
+    alt: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExternalView {
+    uri: Url,
+    title: String,
+    description: String,
+    thumb: Option<Url>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordView {
+    record: QuotedRecord,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum QuotedRecord {
+    #[serde(rename = "app.bsky.embed.record#viewRecord")]
+    Post {
+        author: Author,
+        value: PostRecord,
+
+        #[serde(default)]
+        embeds: Vec<Embed>,
+
+        #[serde(default)]
+        labels: Vec<Label>,
+    },
+
+    /// deleted or blocked posts, lists, feeds, ...
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+enum Embed {
+    #[serde(rename = "app.bsky.embed.images#view")]
+    Images { images: Vec<ImageView> },
+
+    #[serde(rename = "app.bsky.embed.external#view")]
+    External { external: ExternalView },
+
+    #[serde(rename = "app.bsky.embed.video#view")]
+    Video {
+        thumbnail: Option<Url>,
+        alt: Option<String>,
+    },
+
+    #[serde(rename = "app.bsky.embed.record#view")]
+    Record { record: QuotedRecord },
+
+    #[serde(rename = "app.bsky.embed.recordWithMedia#view")]
+    RecordWithMedia { record: RecordView, media: Box<Embed> },
+
+    #[serde(other)]
+    Unknown,
+}
+
+/// Everything collected from a post and the posts it embeds
+#[derive(Default)]
+struct Content {
+    text: Vec<String>,
+    images: Vec<Url>,
+    video_thumbnail: Option<Url>,
+    link_thumbnail: Option<Url>,
+    nsfw: bool,
+}
+
+impl Content {
+    fn has_media(&self) -> bool {
+        !self.images.is_empty() || self.video_thumbnail.is_some()
+    }
+
+    fn add_embed(&mut self, embed: Embed) {
+        match embed {
+            // media of quoted posts is only used if the post itself has none
+            Embed::Images { images } if !self.has_media() => {
+                let n_images = images.len();
+
+                for (ix, image) in images.into_iter().enumerate() {
+                    if !image.alt.is_empty() {
+                        self.text.push(if n_images == 1 {
+                            format!("Image: {alt}", alt = image.alt)
+                        } else {
+                            format!("Image {n}: {alt}", n = ix + 1, alt = image.alt)
+                        });
+                    }
+
+                    self.images.push(image.fullsize);
+                }
+            },
+            Embed::Video { thumbnail, alt } if !self.has_media() => {
+                if let Some(alt) = alt.filter(|alt| !alt.is_empty()) {
+                    self.text.push(format!("Video: {alt}"));
+                }
+
+                self.video_thumbnail = thumbnail;
+            },
+            Embed::External { external } => {
+                self.text.push(format!(
+                    "[{title}]({uri})\n{description}",
+                    title = external.title,
+                    uri = external.uri,
+                    description = external.description
+                ));
+
+                self.link_thumbnail = self.link_thumbnail.take().or(external.thumb);
+            },
+            Embed::Record { record: QuotedRecord::Post { author, value, embeds, labels } } => {
+                let quote = value
+                    .text
+                    .lines()
+                    .map(|line| format!("> {line}"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.text
+                    .push(format!("Quoting {author}:\n{quote}", author = author.fmt_name()));
+                self.nsfw |= has_nsfw_label(&labels);
+
+                for embed in embeds {
+                    self.add_embed(embed);
+                }
+            },
+            Embed::RecordWithMedia { record, media } => {
+                self.add_embed(*media);
+                self.add_embed(Embed::Record { record: record.record });
+            },
+            _ => {},
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiSettings {
+    /// base url of the AppView whose XRPC API is used to fetch posts
+    #[serde(default = "ApiSettings::default_api_base")]
+    pub api_base: Url,
+
+    #[serde(default)]
+    pub http: HttpSettings,
+}
+
+impl ApiSettings {
+    fn default_api_base() -> Url {
+        Url::parse("https://public.api.bsky.app").unwrap()
+    }
+}
+
+pub struct Api {
+    api_base: Url,
+    http: HttpClient,
+}
+
+/// Converts `https://bsky.app/profile/<handle or did>/post/<rkey>` into the AT URI of the post
+fn post_uri(url: &Url) -> Option<String> {
+    let segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    match segments[..] {
+        ["profile", actor, "post", rkey] => Some(format!("at://{actor}/app.bsky.feed.post/{rkey}")),
+        _ => None,
+    }
+}
+
+impl Api {
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self {
+            api_base: settings.api_base,
+            http: http.with_settings(&settings.http)?,
+        })
+    }
+
+    fn thread_url(&self, post_uri: &str) -> Url {
+        let mut url = self.api_base.clone();
+
+        url.path_segments_mut()
+            .expect("api base must be a valid base url")
+            .pop_if_empty()
+            .extend(["xrpc", "app.bsky.feed.getPostThread"]);
+
+        url.query_pairs_mut()
+            .append_pair("uri", post_uri)
+            .append_pair("depth", "0")
+            .append_pair("parentHeight", "0");
+
+        url
+    }
+
+    fn analyze_thread(url: Url, resp: ThreadResponse) -> anyhow::Result<Post> {
+        let Thread::Post { post } = resp.thread else {
+            anyhow::bail!("Post was deleted or is not visible");
+        };
+
+        let post = *post;
+
+        let mut content = Content {
+            text: vec![post.record.text],
+            nsfw: has_nsfw_label(&post.labels),
+            ..Default::default()
+        };
+
+        if let Some(embed) = post.embed {
+            content.add_embed(embed);
+        }
+
+        let Content { text, mut images, video_thumbnail, link_thumbnail, nsfw } = content;
+
+        let specialized = match (images.len(), video_thumbnail, link_thumbnail) {
+            (1, _, _) => PostSpecializedData::Image { img_url: images.swap_remove(0) },
+            (2.., _, _) => {
+                PostSpecializedData::Gallery { images: images.into_iter().map(GalleryImage::from).collect() }
+            },
+            (0, Some(thumbnail_url), _) => PostSpecializedData::VideoThumbnail { thumbnail_url },
+            (0, None, Some(img_url)) => PostSpecializedData::Image { img_url },
+            (0, None, None) => PostSpecializedData::TextOnly,
+        };
+
+        let common = PostCommonData {
+            src: url,
+            origin: "bsky.app".to_owned(),
+            title: post.author.fmt_name(),
+            text: text
+                .into_iter()
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            nsfw,
+            spoiler: false,
+            comment: None,
+        };
+
+        Ok(Post { common, specialized })
+    }
+}
+
+#[async_trait]
+impl PostScraper for Api {
+    fn is_suitable(&self, url: &Url) -> bool {
+        url.domain() == Some("bsky.app") && post_uri(url).is_some()
+    }
+
+    async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
+        let post_uri = post_uri(&url).ok_or_else(|| anyhow::anyhow!("Url does not point to a post"))?;
+
+        let resp = self
+            .http
+            .get(self.thread_url(&post_uri))
+            .await?
+            .json()
+            .await
+            .context("Unable to parse post thread")?;
+
+        Self::analyze_thread(url, resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    fn analyze(url: &str, json: &str) -> Post {
+        Api::analyze_thread(Url::from_str(url).unwrap(), serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn images_post() {
+        const JSON: &str = include_str!("../../test_data/bluesky/images.json");
+        let url = "https://bsky.app/profile/alice.bsky.social/post/3kgcsbxuwrk2d";
+
+        let expected = Post {
+            common: PostCommonData {
+                src: Url::from_str(url).unwrap(),
+                origin: "bsky.app".to_owned(),
+                title: "Alice (@alice.bsky.social)".to_owned(),
+                text: "Two pictures from today's hike\n\nImage 1: A mountain lake".to_owned(),
+                nsfw: true,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Gallery {
+                images: vec![
+                    Url::from_str(
+                        "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:z72i7hdynmk6r22z27h6tvur/bafkreia@jpeg",
+                    )
+                    .unwrap()
+                    .into(),
+                    Url::from_str(
+                        "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:z72i7hdynmk6r22z27h6tvur/bafkreib@jpeg",
+                    )
+                    .unwrap()
+                    .into(),
+                ],
+            },
+        };
+
+        assert_eq!(expected, analyze(url, JSON));
+    }
+
+    #[test]
+    fn quote_with_link_card() {
+        const JSON: &str = include_str!("../../test_data/bluesky/quote.json");
+        let post = analyze("https://bsky.app/profile/bob.example.com/post/3l3qo2vuowo2b", JSON);
+
+        assert_eq!(post.common.title, "@bob.example.com");
+        assert_eq!(
+            post.common.text,
+            "Worth a read\n\n[An article](https://example.com/article)\nWhat the article is about\n\n\
+             Quoting Alice (@alice.bsky.social):\n> I wrote something"
+        );
+        assert!(!post.common.nsfw);
+        assert_eq!(
+            post.specialized,
+            PostSpecializedData::Image {
+                img_url: Url::from_str(
+                    "https://cdn.bsky.app/img/feed_thumbnail/plain/did:plc:ewvi7nxzyoun6zhxrhs64oiz/bafkreic@jpeg"
+                )
+                .unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn video_post() {
+        const JSON: &str = include_str!("../../test_data/bluesky/video.json");
+        let post = analyze("https://bsky.app/profile/alice.bsky.social/post/3lbi4xbwbwk2c", JSON);
+
+        assert_eq!(
+            post.common.text,
+            "Look at this cat\n\nVideo: A cat chasing a laser pointer"
+        );
+        assert_eq!(
+            post.specialized,
+            PostSpecializedData::VideoThumbnail {
+                thumbnail_url: Url::from_str(
+                    "https://video.bsky.app/watch/did%3Aplc%3Az72i7hdynmk6r22z27h6tvur/bafkreid/thumbnail.jpg"
+                )
+                .unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn post_urls() {
+        let uri = |url: &str| post_uri(&Url::from_str(url).unwrap());
+
+        assert_eq!(
+            uri("https://bsky.app/profile/alice.bsky.social/post/3kgcsbxuwrk2d/"),
+            Some("at://alice.bsky.social/app.bsky.feed.post/3kgcsbxuwrk2d".to_owned())
+        );
+        assert_eq!(uri("https://bsky.app/profile/alice.bsky.social"), None);
+    }
+
+    #[tokio::test]
+    async fn scrape_from_local_appview() {
+        const JSON: &str = include_str!("../../test_data/bluesky/video.json");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let appview = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{JSON}",
+                JSON.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        let api = Api::from_settings(
+            ApiSettings { api_base, http: HttpSettings::default() },
+            &HttpClient::new().unwrap(),
+        )
+        .unwrap();
+
+        let url = Url::from_str("https://bsky.app/profile/alice.bsky.social/post/3lbi4xbwbwk2c").unwrap();
+        assert!(api.is_suitable(&url));
+
+        let post = api.scrape_post(url).await.unwrap();
+        assert_eq!(post.common.title, "Alice (@alice.bsky.social)");
+
+        let request = appview.await.unwrap();
+        assert!(request.starts_with(
+            "GET /xrpc/app.bsky.feed.getPostThread?uri=at%3A%2F%2Falice.bsky.social%2Fapp.bsky.feed.post%2F3lbi4xbwbwk2c&depth=0&parentHeight=0 HTTP/1.1\r\n"
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::Context;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::{borrow::Cow, time::Duration};
+use url::Url;
+
+/// A single quality level of a track
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Representation {
+    /// bits per second
+    pub bandwidth: u64,
+    pub url: Url,
+}
+
+impl Representation {
+    /// The expected size in bytes of `duration` worth of this representation
+    pub fn estimated_size(&self, duration: Duration) -> u64 {
+        (self.bandwidth as f64 * duration.as_secs_f64() / 8.0) as u64
+    }
+}
+
+/// The parts of an MPEG-DASH manifest needed to download a complete video,
+/// representations are sorted from highest to lowest bandwidth
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub duration: Option<Duration>,
+    pub video: Vec<Representation>,
+    pub audio: Vec<Representation>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TrackKind {
+    Video,
+    Audio,
+}
+
+impl TrackKind {
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime.split('/').next()? {
+            "video" => Some(Self::Video),
+            "audio" => Some(Self::Audio),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an ISO 8601 duration of the form `PT1H2M3.5S`
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s.strip_prefix("PT")?;
+    let mut secs = 0.0;
+
+    while !rest.is_empty() {
+        let unit_pos = rest.find(|c: char| c.is_ascii_alphabetic())?;
+        let value: f64 = rest[..unit_pos].parse().ok()?;
+
+        secs += value
+            * match &rest[unit_pos..=unit_pos] {
+                "H" => 3600.0,
+                "M" => 60.0,
+                "S" => 1.0,
+                _ => return None,
+            };
+
+        rest = &rest[unit_pos + 1..];
+    }
+
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// The state of an element that is still open, inherited by its children
+#[derive(Clone)]
+struct Scope {
+    base_url: Url,
+    /// whether `base_url` was set by a `BaseURL` element instead of being the manifest url
+    has_base_url: bool,
+    /// whether this element already had a `BaseURL` element, any further ones are only alternatives
+    own_base_url: bool,
+    kind: Option<TrackKind>,
+    bandwidth: u64,
+}
+
+fn attribute(element: &BytesStart, name: &str) -> anyhow::Result<Option<String>> {
+    Ok(element
+        .try_get_attribute(name)?
+        .map(|a| a.unescape_value())
+        .transpose()?
+        .map(Cow::into_owned))
+}
+
+impl Manifest {
+    /// Parses the manifest served at `manifest_url`, which the relative urls in it are resolved against.
+    /// Only manifests with a single period whose representations are each one file are supported.
+    pub fn parse(manifest_url: &Url, mpd: &str) -> anyhow::Result<Self> {
+        let mut manifest = Self::default();
+        let mut reader = Reader::from_str(mpd);
+        reader.config_mut().trim_text(true);
+
+        let mut scopes = vec![Scope {
+            base_url: manifest_url.clone(),
+            has_base_url: false,
+            own_base_url: false,
+            kind: None,
+            bandwidth: 0,
+        }];
+        let mut periods = 0;
+        let mut in_base_url = false;
+
+        loop {
+            let (element, empty) = match reader.read_event()? {
+                Event::Text(text) if in_base_url => {
+                    let scope = scopes
+                        .last_mut()
+                        .context("Content after the end of the DASH manifest")?;
+
+                    if !scope.own_base_url {
+                        scope.base_url = scope.base_url.join(&text.unescape()?)?;
+                        scope.has_base_url = true;
+                        scope.own_base_url = true;
+                    }
+
+                    continue;
+                },
+                // the url of a `BaseURL` element belongs to the element containing it, so it gets no scope of its own
+                Event::Start(element) if element.local_name().as_ref() == b"BaseURL" => {
+                    in_base_url = true;
+                    continue;
+                },
+                Event::End(element) if element.local_name().as_ref() == b"BaseURL" => {
+                    in_base_url = false;
+                    continue;
+                },
+                Event::Start(element) => (element, false),
+                Event::Empty(element) => (element, true),
+                Event::End(element) => {
+                    let scope = scopes.pop().context("Unbalanced DASH manifest")?;
+
+                    if element.local_name().as_ref() == b"Representation" {
+                        manifest.push(scope)?;
+                    }
+
+                    continue;
+                },
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            let parent = scopes.last().context("Content after the end of the DASH manifest")?;
+            let mut scope = Scope { own_base_url: false, ..parent.clone() };
+
+            match element.local_name().as_ref() {
+                b"MPD" => {
+                    manifest.duration = attribute(&element, "mediaPresentationDuration")?
+                        .as_deref()
+                        .and_then(parse_duration);
+                },
+                b"Period" => {
+                    periods += 1;
+                    anyhow::ensure!(periods == 1, "DASH manifests with multiple periods are not supported");
+
+                    if manifest.duration.is_none() {
+                        manifest.duration = attribute(&element, "duration")?.as_deref().and_then(parse_duration);
+                    }
+                },
+                b"AdaptationSet" | b"Representation" => {
+                    let kind = match attribute(&element, "contentType")? {
+                        Some(content_type) => Some(content_type),
+                        None => attribute(&element, "mimeType")?,
+                    };
+
+                    if let Some(kind) = kind.as_deref().and_then(TrackKind::from_mime) {
+                        scope.kind = Some(kind);
+                    }
+
+                    if let Some(bandwidth) = attribute(&element, "bandwidth")? {
+                        scope.bandwidth = bandwidth.parse().context("Invalid bandwidth in DASH manifest")?;
+                    }
+                },
+                b"SegmentTemplate" | b"SegmentList" => {
+                    anyhow::bail!("DASH manifests with segmented representations are not supported");
+                },
+                _ => {},
+            }
+
+            if empty {
+                if element.local_name().as_ref() == b"Representation" {
+                    manifest.push(scope)?;
+                }
+            } else {
+                scopes.push(scope);
+            }
+        }
+
+        manifest.video.sort_by_key(|r| std::cmp::Reverse(r.bandwidth));
+        manifest.audio.sort_by_key(|r| std::cmp::Reverse(r.bandwidth));
+
+        Ok(manifest)
+    }
+
+    fn push(&mut self, representation: Scope) -> anyhow::Result<()> {
+        let track = match representation.kind {
+            Some(TrackKind::Video) => &mut self.video,
+            Some(TrackKind::Audio) => &mut self.audio,
+            // e.g. subtitles
+            None => return Ok(()),
+        };
+
+        anyhow::ensure!(representation.has_base_url, "DASH representation without a BaseURL");
+
+        track.push(Representation {
+            bandwidth: representation.bandwidth,
+            url: representation.base_url,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("PT17.5S"), Some(Duration::from_secs_f64(17.5)));
+        assert_eq!(parse_duration("PT1H2M3S"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("P1D"), None);
+    }
+
+    #[test]
+    fn reddit_manifest() {
+        const MPD: &str = include_str!("../../test_data/reddit/DASHPlaylist.mpd");
+
+        let manifest_url = Url::parse("https://v.redd.it/jx4ua6lirla71/DASHPlaylist.mpd?a=1&v=1&f=sd").unwrap();
+        let manifest = Manifest::parse(&manifest_url, MPD).unwrap();
+
+        let repr = |bandwidth, path: &str| Representation {
+            bandwidth,
+            url: Url::parse(&format!("https://v.redd.it/jx4ua6lirla71/{path}")).unwrap(),
+        };
+
+        let expected = Manifest {
+            duration: Some(Duration::from_secs_f64(17.5)),
+            video: vec![
+                repr(4_800_000, "DASH_1080.mp4?source=fallback"),
+                repr(2_400_000, "DASH_720.mp4?source=fallback"),
+                repr(1_200_000, "DASH_480.mp4?source=fallback"),
+            ],
+            audio: vec![repr(128_000, "DASH_audio.mp4")],
+        };
+
+        assert_eq!(expected, manifest);
+    }
+
+    #[test]
+    fn nested_base_urls() {
+        const MPD: &str = include_str!("../../test_data/dash/nested_base_urls.synthetic.mpd");
+
+        let manifest_url = Url::parse("https://example.com/watch/abc123/manifest.mpd").unwrap();
+        let manifest = Manifest::parse(&manifest_url, MPD).unwrap();
+
+        let repr = |bandwidth, url: &str| Representation { bandwidth, url: Url::parse(url).unwrap() };
+
+        let expected = Manifest {
+            duration: Some(Duration::from_secs(64)),
+            video: vec![
+                repr(
+                    2_500_000,
+                    "https://cdn.example.com/media/abc123/video/720.mp4?token=a&expires=1",
+                ),
+                repr(800_000, "https://cdn.example.com/media/abc123/video/360.mp4"),
+            ],
+            audio: vec![repr(96_000, "https://cdn.example.com/audio/abc123.mp4")],
+        };
+
+        assert_eq!(expected, manifest);
+    }
+
+    #[test]
+    fn unsupported_manifests() {
+        const SEGMENTED: &str = include_str!("../../test_data/dash/segment_template.synthetic.mpd");
+
+        const MULTIPLE_PERIODS: &str = r#"<MPD><Period><BaseURL>a.mp4</BaseURL></Period><Period/></MPD>"#;
+
+        const WITHOUT_BASE_URL: &str = r#"<MPD><Period><AdaptationSet mimeType="video/mp4"><Representation bandwidth="1"/></AdaptationSet></Period></MPD>"#;
+
+        let manifest_url = Url::parse("https://example.com/manifest.mpd").unwrap();
+
+        for mpd in [SEGMENTED, MULTIPLE_PERIODS, WITHOUT_BASE_URL] {
+            assert!(Manifest::parse(&manifest_url, mpd).is_err(), "{mpd}");
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::scraper::public_host::{self, is_public_https_url, PublicResolver};
+use anyhow::Context;
+use reqwest::{header::RETRY_AFTER, IntoUrl, Proxy, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+
+const USER_AGENT: &str = concat!("github.com/liss-h/embedbot-rs embedbot/", clap::crate_version!());
+
+/// pages are only read up to this size when just their `<head>` is of interest
+const MAX_HEAD_SIZE: usize = 1024 * 1024;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpSettings {
+    /// timeout in seconds for a single request, including reading the response body
+    pub timeout_secs: Option<u64>,
+
+    /// how many times a request is retried after the server responded with 429 or 5xx
+    pub retries: u32,
+
+    pub user_agent: Option<String>,
+
+    /// http(s) or socks5 proxy to send all requests of the module through
+    pub proxy: Option<Url>,
+}
+
+/// A cheaply cloneable http client.
+/// All clones share a single connection pool, unless they were configured to use a different proxy.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    timeout: Option<Duration>,
+    retries: u32,
+    user_agent: String,
+    proxy: Option<Url>,
+    public_only: bool,
+}
+
+fn build_client(proxy: Option<&Url>, public_only: bool) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str()).context("Invalid proxy url")?);
+    } else if public_only {
+        // a proxy from the environment would resolve the hosts itself
+        builder = builder.no_proxy();
+    }
+
+    if public_only {
+        builder = builder
+            .dns_resolver(PublicResolver)
+            .redirect(public_host::redirect_policy());
+    }
+
+    builder.build().context("Unable to create http client")
+}
+
+impl HttpClient {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            client: build_client(None, false)?,
+            timeout: None,
+            retries: 0,
+            user_agent: USER_AGENT.to_owned(),
+            proxy: None,
+            public_only: false,
+        })
+    }
+
+    /// Creates a client for a single module, the connection pool is shared with `self` unless a proxy is configured
+    pub fn with_settings(&self, settings: &HttpSettings) -> anyhow::Result<Self> {
+        let client = match &settings.proxy {
+            Some(proxy) => build_client(Some(proxy), self.public_only)?,
+            None => self.client.clone(),
+        };
+
+        Ok(Self {
+            client,
+            timeout: settings.timeout_secs.map(Duration::from_secs).or(self.timeout),
+            retries: settings.retries,
+            user_agent: settings.user_agent.clone().unwrap_or_else(|| self.user_agent.clone()),
+            proxy: settings.proxy.clone().or_else(|| self.proxy.clone()),
+            public_only: self.public_only,
+        })
+    }
+
+    /// Creates a client that only fetches public https urls, also when following redirects, for urls that
+    /// come from untrusted sources. Through a proxy only the host names can be checked, not their addresses.
+    pub fn public_only(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: build_client(self.proxy.as_ref(), true)?,
+            public_only: true,
+            ..self.clone()
+        })
+    }
+
+    pub async fn get<U: IntoUrl>(&self, url: U) -> anyhow::Result<Response> {
+        self.send_get(url, None).await
+    }
+
+    /// Like [`Self::get`] but asks the server for a specific representation via the `Accept` header
+    pub async fn get_accepting<U: IntoUrl>(&self, url: U, accept: &str) -> anyhow::Result<Response> {
+        self.send_get(url, Some(accept)).await
+    }
+
+    async fn send_get<U: IntoUrl>(&self, url: U, accept: Option<&str>) -> anyhow::Result<Response> {
+        let url = url.into_url().context("Invalid url")?;
+
+        if self.public_only {
+            anyhow::ensure!(
+                is_public_https_url(&url),
+                "Refusing to fetch {url}, it is not a public https url"
+            );
+        }
+
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.get(url.clone()).header("User-Agent", &self.user_agent);
+
+            if let Some(accept) = accept {
+                request = request.header("Accept", accept);
+            }
+
+            if let Some(timeout) = self.timeout {
+                request = request.timeout(timeout);
+            }
+
+            let resp = request.send().await.context("Unable to fetch web page")?;
+
+            if attempt < self.retries && is_retryable(resp.status()) {
+                let delay = retry_delay(attempt, &resp);
+                tracing::debug!("{url} responded with {}, retrying in {delay:?}", resp.status());
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+
+            return resp.error_for_status().context("Server responded with error code");
+        }
+    }
+
+    /// Fetches an html page up to the end of its `<head>`, without reading more than [`MAX_HEAD_SIZE`] bytes
+    pub async fn get_html_head<U: IntoUrl>(&self, url: U) -> anyhow::Result<String> {
+        let mut resp = self.get(url).await?;
+        let mut data = Vec::new();
+
+        while let Some(chunk) = resp.chunk().await.context("Unable to read web page")? {
+            // the closing tag may be split across chunks
+            let search_from = data.len().saturating_sub(HEAD_END.len() - 1);
+            data.extend_from_slice(&chunk);
+
+            if let Some(end) = find_head_end(&data[search_from..]) {
+                data.truncate(search_from + end);
+                break;
+            }
+
+            if data.len() >= MAX_HEAD_SIZE {
+                data.truncate(MAX_HEAD_SIZE);
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    pub async fn get_json<U: IntoUrl>(&self, url: U) -> anyhow::Result<serde_json::Value> {
+        self.get(url)
+            .await?
+            .json()
+            .await
+            .context("Unable to parse response as json")
+    }
+}
+
+const HEAD_END: &[u8] = b"</head>";
+
+/// The position right after the first `</head>` in `data`
+fn find_head_end(data: &[u8]) -> Option<usize> {
+    data.windows(HEAD_END.len())
+        .position(|w| w.eq_ignore_ascii_case(HEAD_END))
+        .map(|pos| pos + HEAD_END.len())
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn backoff(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RETRY_MAX_DELAY)
+}
+
+fn retry_delay(attempt: u32, resp: &Response) -> Duration {
+    let retry_after = resp
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse().ok())
+        .map(Duration::from_secs);
+
+    retry_after.map_or_else(|| backoff(attempt), |d| d.min(RETRY_MAX_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(10), RETRY_MAX_DELAY);
+        assert_eq!(backoff(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn head_end() {
+        assert_eq!(find_head_end(b"<html><HEAD></HEAD><body>"), Some(19));
+        assert_eq!(find_head_end(b"<html><head></he"), None);
+    }
+
+    #[test]
+    fn settings_keys() {
+        let settings: HttpSettings = toml::from_str("timeout-secs = 10\nretries = 2").unwrap();
+        assert_eq!(settings.timeout_secs, Some(10));
+        assert_eq!(settings.retries, 2);
+
+        assert!(toml::from_str::<HttpSettings>("timeout_secs = 10").is_err());
+    }
+}
@@ -0,0 +1,376 @@
+#![cfg(feature = "mastodon")]
+
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    media_to_specialized,
+    public_host::is_public_https_url,
+    util::url_domain_matches,
+    Media, Post, PostCommonData, PostScraper,
+};
+use anyhow::Context;
+use scraper::{ElementRef, Html, Node};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::async_trait;
+use url::Url;
+
+const ACTIVITY_JSON: &str = "application/activity+json";
+
+/// Extracts the status id from urls of the form `/@user/<id>` or `/users/<user>/statuses/<id>`
+fn status_id(url: &Url) -> Option<&str> {
+    let segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    let id = match segments[..] {
+        [user, id] if user.starts_with('@') => id,
+        ["users", _, "statuses", id] => id,
+        _ => return None,
+    };
+
+    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())).then_some(id)
+}
+
+fn push_text(element: ElementRef, text: &mut String) {
+    for child in element.children() {
+        if let Some(child) = ElementRef::wrap(child) {
+            match child.value().name() {
+                "br" => text.push('\n'),
+                "p" => {
+                    push_text(child, text);
+                    text.push_str("\n\n");
+                },
+                _ => push_text(child, text),
+            }
+        } else if let Node::Text(t) = child.value() {
+            text.push_str(t);
+        }
+    }
+}
+
+/// Converts the html of a status into plain text, keeping paragraphs and line breaks
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    push_text(Html::parse_fragment(html).root_element(), &mut text);
+
+    text.trim_end().to_owned()
+}
+
+/// A status as returned by the Mastodon API, see <https://docs.joinmastodon.org/entities/Status/>
+#[derive(Debug, Deserialize)]
+struct Status {
+    account: Account,
+    content: String,
+    sensitive: bool,
+    spoiler_text: String,
+    media_attachments: Vec<MediaAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    acct: String,
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaAttachment {
+    #[serde(rename = "type")]
+    kind: String,
+    url: Url,
+}
+
+/// An ActivityPub `Note`, see <https://www.w3.org/TR/activitystreams-vocabulary/#dfn-note>
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Note {
+    attributed_to: Url,
+    content: String,
+
+    #[serde(default)]
+    sensitive: bool,
+
+    summary: Option<String>,
+
+    #[serde(default)]
+    attachment: Vec<NoteAttachment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteAttachment {
+    media_type: Option<String>,
+
+    /// either a plain url, a `Link` object or a list of those
+    url: Value,
+}
+
+impl NoteAttachment {
+    fn url(&self) -> Option<Url> {
+        let link = match &self.url {
+            Value::Array(links) => links.first()?,
+            link => link,
+        };
+
+        let href = match link {
+            Value::String(href) => href,
+            Value::Object(link) => link.get("href")?.as_str()?,
+            _ => return None,
+        };
+
+        Url::parse(href).ok()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ApiSettings {
+    /// domains (including their subdomains) whose urls are never treated as fediverse statuses
+    #[serde(default)]
+    pub ignored_domains: Vec<String>,
+
+    #[serde(default)]
+    pub http: HttpSettings,
+}
+
+pub struct Api {
+    ignored_domains: Vec<String>,
+    http: HttpClient,
+}
+
+impl Api {
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self {
+            ignored_domains: settings.ignored_domains,
+            // checks every address and redirect, which is_suitable cannot do for the posted url
+            http: http.with_settings(&settings.http)?.public_only()?,
+        })
+    }
+
+    fn make_common(url: Url, title: String, content: &str, sensitive: bool, content_warning: &str) -> PostCommonData {
+        let text = html_to_text(content);
+
+        PostCommonData {
+            origin: url.host_str().unwrap_or_default().to_owned(),
+            title,
+            text: if content_warning.is_empty() {
+                text
+            } else {
+                format!("CW: {content_warning}\n\n{text}")
+            },
+            // statuses with a content warning are always marked as sensitive
+            nsfw: sensitive && content_warning.is_empty(),
+            spoiler: !content_warning.is_empty(),
+            comment: None,
+            src: url,
+        }
+    }
+
+    fn analyze_status(url: Url, status: Status) -> Post {
+        let instance = url.host_str().unwrap_or_default();
+
+        let acct = if status.account.acct.contains('@') {
+            status.account.acct
+        } else {
+            format!("{acct}@{instance}", acct = status.account.acct)
+        };
+
+        let title = if status.account.display_name.is_empty() {
+            format!("@{acct}")
+        } else {
+            format!("{name} (@{acct})", name = status.account.display_name)
+        };
+
+        let media = status
+            .media_attachments
+            .into_iter()
+            .filter_map(|m| match m.kind.as_str() {
+                "image" => Some(Media::Image(m.url)),
+                "video" | "gifv" => Some(Media::Video(m.url)),
+                _ => None,
+            })
+            .collect();
+
+        Post {
+            common: Self::make_common(url, title, &status.content, status.sensitive, &status.spoiler_text),
+            specialized: media_to_specialized(media),
+        }
+    }
+
+    fn analyze_note(url: Url, note: Note) -> Post {
+        let author = {
+            let user = note
+                .attributed_to
+                .path_segments()
+                .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+                .unwrap_or_default();
+
+            format!(
+                "@{user}@{host}",
+                host = note.attributed_to.host_str().unwrap_or_default()
+            )
+        };
+
+        let media = note
+            .attachment
+            .iter()
+            .filter_map(|a| {
+                let url = a.url()?;
+
+                match a.media_type.as_deref()?.split('/').next()? {
+                    "image" => Some(Media::Image(url)),
+                    "video" => Some(Media::Video(url)),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        Post {
+            common: Self::make_common(
+                url,
+                author,
+                &note.content,
+                note.sensitive,
+                note.summary.as_deref().unwrap_or_default(),
+            ),
+            specialized: media_to_specialized(media),
+        }
+    }
+}
+
+#[async_trait]
+impl PostScraper for Api {
+    fn is_suitable(&self, url: &Url) -> bool {
+        // any host could be an instance, so at least keep posted urls from reaching internal services
+        is_public_https_url(url)
+            && status_id(url).is_some()
+            && !self.ignored_domains.iter().any(|d| url_domain_matches(url, d))
+    }
+
+    async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
+        let id = status_id(&url).ok_or_else(|| anyhow::anyhow!("Url does not point to a status"))?;
+
+        let mut api_url = url.clone();
+        api_url.set_path(&format!("/api/v1/statuses/{id}"));
+        api_url.set_query(None);
+
+        let status = match self.http.get(api_url).await {
+            Ok(resp) => resp.json::<Status>().await.ok(),
+            Err(e) => {
+                tracing::debug!("Mastodon API unavailable for {url}: {e:#}");
+                None
+            },
+        };
+
+        if let Some(status) = status {
+            return Ok(Self::analyze_status(url, status));
+        }
+
+        // not a mastodon instance or the API requires authentication, try ActivityPub instead
+        let note = self
+            .http
+            .get_accepting(url.clone(), ACTIVITY_JSON)
+            .await?
+            .json()
+            .await
+            .context("Unable to parse ActivityPub object")?;
+
+        Ok(Self::analyze_note(url, note))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::PostSpecializedData;
+    use std::str::FromStr;
+
+    #[test]
+    fn status_urls() {
+        let id = |url: &str| status_id(&Url::from_str(url).unwrap()).map(ToOwned::to_owned);
+
+        assert_eq!(
+            id("https://mastodon.social/@alice/112345678901234567"),
+            Some("112345678901234567".to_owned())
+        );
+        assert_eq!(
+            id("https://chaos.social/@bob@mastodon.social/1234/"),
+            Some("1234".to_owned())
+        );
+        assert_eq!(
+            id("https://mastodon.social/users/alice/statuses/1234"),
+            Some("1234".to_owned())
+        );
+        assert_eq!(id("https://mastodon.social/@alice"), None);
+        assert_eq!(id("https://medium.com/@alice/some-article-1234"), None);
+    }
+
+    #[test]
+    fn internal_urls_are_not_suitable() {
+        let api = Api::from_settings(ApiSettings::default(), &HttpClient::new().unwrap()).unwrap();
+        let suitable = |url: &str| api.is_suitable(&Url::from_str(url).unwrap());
+
+        assert!(suitable("https://mastodon.social/@alice/1234"));
+        assert!(!suitable("http://mastodon.social/@alice/1234"));
+        assert!(!suitable("http://127.0.0.1:8080/@a/1"));
+        assert!(!suitable("https://169.254.169.254/@a/1"));
+        assert!(!suitable("https://[fd00::1]/users/a/statuses/1"));
+        assert!(!suitable("https://localhost/@a/1"));
+        assert!(!suitable("https://mastodon.social:8080/@alice/1234"));
+    }
+
+    #[test]
+    fn status() {
+        const JSON: &str = include_str!("../../test_data/mastodon/status.json");
+
+        let url = Url::from_str("https://mastodon.social/@alice/112345678901234567").unwrap();
+        let post = Api::analyze_status(url.clone(), serde_json::from_str(JSON).unwrap());
+
+        let expected = Post {
+            common: PostCommonData {
+                src: url,
+                origin: "mastodon.social".to_owned(),
+                title: "Alice (@alice@mastodon.social)".to_owned(),
+                text: "Some photos from the #zoo & more\n\nsecond\nparagraph https://example.com/a/very/long/path"
+                    .to_owned(),
+                nsfw: true,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Gallery {
+                images: vec![
+                    Url::from_str("https://files.mastodon.social/media_attachments/files/1/original/a.jpg")
+                        .unwrap()
+                        .into(),
+                    Url::from_str("https://files.mastodon.social/media_attachments/files/2/original/b.jpg")
+                        .unwrap()
+                        .into(),
+                ],
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+
+    #[test]
+    fn activitypub_note() {
+        const JSON: &str = include_str!("../../test_data/mastodon/note.json");
+
+        let url = Url::from_str("https://social.example.org/users/bob/statuses/109876543210").unwrap();
+        let post = Api::analyze_note(url.clone(), serde_json::from_str(JSON).unwrap());
+
+        let expected = Post {
+            common: PostCommonData {
+                src: url,
+                origin: "social.example.org".to_owned(),
+                title: "@bob@social.example.org".to_owned(),
+                text: "CW: spoilers for the finale\n\nI can't believe how it ended".to_owned(),
+                nsfw: false,
+                spoiler: true,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Video {
+                video_url: Url::from_str("https://social.example.org/system/media_attachments/files/3/original/c.mp4")
+                    .unwrap(),
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+}
@@ -1,18 +1,26 @@
+pub mod bluesky;
+pub mod dash;
+pub mod http;
+pub mod mastodon;
 pub mod ninegag;
+pub mod oembed;
+pub mod opengraph;
+mod public_host;
 pub mod reddit;
 pub mod twitter;
-mod util;
+pub mod util;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serenity::async_trait;
 use url::Url;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Comment {
     pub author: String,
     pub text: String,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PostCommonData {
     pub src: Url,
     pub origin: String,
@@ -23,13 +31,77 @@ pub struct PostCommonData {
     pub comment: Option<Comment>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GalleryImage {
+    pub url: Url,
+    pub caption: Option<String>,
+
+    /// where the poster wants the image to lead to
+    pub link: Option<Url>,
+}
+
+impl From<Url> for GalleryImage {
+    fn from(url: Url) -> Self {
+        Self { url, caption: None, link: None }
+    }
+}
+
+/// Serialized with the variant name in `kind`, e.g. `{ "kind": "image", "img_url": "..." }`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
 pub enum PostSpecializedData {
     TextOnly,
-    Gallery { img_urls: Vec<Url> },
-    Image { img_url: Url },
-    Video { video_url: Url },
-    VideoThumbnail { thumbnail_url: Url },
+    Gallery {
+        images: Vec<GalleryImage>,
+    },
+    Image {
+        img_url: Url,
+    },
+    Video {
+        video_url: Url,
+    },
+
+    /// a video split into separate video and audio tracks described by an MPEG-DASH manifest,
+    /// `video_url` is a video-only stream that can be linked if the tracks cannot be combined
+    DashVideo {
+        video_url: Url,
+        manifest_url: Url,
+    },
+
+    VideoThumbnail {
+        thumbnail_url: Url,
+    },
+}
+
+/// A single media item of a post, for sites that allow mixing images and videos
+#[cfg(any(feature = "twitter", feature = "mastodon"))]
+enum Media {
+    Image(Url),
+    Video(Url),
+    VideoThumbnail(Url),
+}
+
+/// Picks the representation discord can show for the media of a post
+#[cfg(any(feature = "twitter", feature = "mastodon"))]
+fn media_to_specialized(media: Vec<Media>) -> PostSpecializedData {
+    let mut img_urls = Vec::new();
+
+    for m in media {
+        match m {
+            // discord can only show a single video, so it takes precedence over any images
+            Media::Video(video_url) => return PostSpecializedData::Video { video_url },
+            Media::VideoThumbnail(thumbnail_url) => return PostSpecializedData::VideoThumbnail { thumbnail_url },
+            Media::Image(img_url) => img_urls.push(img_url),
+        }
+    }
+
+    match img_urls.len() {
+        0 => PostSpecializedData::TextOnly,
+        1 => PostSpecializedData::Image { img_url: img_urls.swap_remove(0) },
+        _ => PostSpecializedData::Gallery {
+            images: img_urls.into_iter().map(GalleryImage::from).collect(),
+        },
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -38,8 +110,121 @@ pub struct Post {
     pub specialized: PostSpecializedData,
 }
 
+impl Post {
+    /// Stored as `version` alongside serialized posts, has to be bumped on every incompatible change to the
+    /// serialized form of a post
+    pub const FORMAT_VERSION: u32 = 1;
+}
+
+#[derive(Serialize)]
+struct SerializedPostRef<'a> {
+    version: u32,
+    common: &'a PostCommonData,
+    specialized: &'a PostSpecializedData,
+}
+
+#[derive(Deserialize)]
+struct SerializedPost {
+    version: u32,
+    common: PostCommonData,
+    specialized: PostSpecializedData,
+}
+
+impl Serialize for Post {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedPostRef {
+            version: Self::FORMAT_VERSION,
+            common: &self.common,
+            specialized: &self.specialized,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Post {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let post = SerializedPost::deserialize(deserializer)?;
+
+        if post.version != Self::FORMAT_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported post format version {}, expected {}",
+                post.version,
+                Self::FORMAT_VERSION
+            )));
+        }
+
+        Ok(Self { common: post.common, specialized: post.specialized })
+    }
+}
+
 #[async_trait]
 pub trait PostScraper {
     fn is_suitable(&self, url: &Url) -> bool;
     async fn scrape_post(&self, url: Url) -> anyhow::Result<Post>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn post() -> Post {
+        Post {
+            common: PostCommonData {
+                src: Url::parse("https://example.com/post").unwrap(),
+                origin: "example.com".to_owned(),
+                title: "title".to_owned(),
+                text: "text".to_owned(),
+                nsfw: false,
+                spoiler: true,
+                comment: Some(Comment { author: "author".to_owned(), text: "comment".to_owned() }),
+            },
+            specialized: PostSpecializedData::DashVideo {
+                video_url: Url::parse("https://example.com/video.mp4").unwrap(),
+                manifest_url: Url::parse("https://example.com/video.mpd").unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn serialized_format() {
+        let expected = json!({
+            "version": 1,
+            "common": {
+                "src": "https://example.com/post",
+                "origin": "example.com",
+                "title": "title",
+                "text": "text",
+                "nsfw": false,
+                "spoiler": true,
+                "comment": { "author": "author", "text": "comment" },
+            },
+            "specialized": {
+                "kind": "dash-video",
+                "video_url": "https://example.com/video.mp4",
+                "manifest_url": "https://example.com/video.mpd",
+            },
+        });
+
+        assert_eq!(serde_json::to_value(post()).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<Post>(expected).unwrap(), post());
+
+        let text_only = serde_json::to_value(PostSpecializedData::TextOnly).unwrap();
+        assert_eq!(text_only, json!({ "kind": "text-only" }));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut value = serde_json::to_value(post()).unwrap();
+        value["version"] = json!(Post::FORMAT_VERSION + 1);
+
+        assert!(serde_json::from_value::<Post>(value).is_err());
+    }
+
+    #[test]
+    fn msgpack_roundtrip() {
+        // internally tagged enums need the map representation of structs
+        let bytes = rmp_serde::to_vec_named(&post()).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Post>(&bytes).unwrap(), post());
+    }
+}
@@ -1,25 +1,33 @@
 #![cfg(feature = "ninegag")]
 
-use crate::scraper::{util::wget, Post, PostCommonData, PostScraper, PostSpecializedData};
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    Post, PostCommonData, PostScraper, PostSpecializedData,
+};
 use json_nav::json_nav;
 use reqwest::IntoUrl;
 use serde::{Deserialize, Serialize};
 use serenity::async_trait;
 use url::Url;
 
-async fn wget_html<U: IntoUrl>(url: U) -> anyhow::Result<scraper::Html> {
-    let resp = wget(url).await?;
+async fn wget_html<U: IntoUrl>(http: &HttpClient, url: U) -> anyhow::Result<scraper::Html> {
+    let resp = http.get(url).await?;
     Ok(scraper::Html::parse_document(&resp.text().await?))
 }
 
 #[derive(Default, Debug, Deserialize, Serialize)]
-pub struct ApiSettings {}
+pub struct ApiSettings {
+    #[serde(default)]
+    pub http: HttpSettings,
+}
 
-pub struct Api;
+pub struct Api {
+    http: HttpClient,
+}
 
 impl Api {
-    pub fn from_settings(_settings: ApiSettings) -> Self {
-        Self
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self { http: http.with_settings(&settings.http)? })
     }
 }
 
@@ -30,7 +38,7 @@ impl PostScraper for Api {
     }
 
     async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
-        let html = wget_html(url.clone()).await?;
+        let html = wget_html(&self.http, url.clone()).await?;
 
         let title: String = {
             let title_selector = scraper::Selector::parse("title").unwrap();
@@ -0,0 +1,421 @@
+#![cfg(feature = "oembed")]
+
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    util::url_domain_matches,
+    Post, PostCommonData, PostScraper, PostSpecializedData,
+};
+use anyhow::Context;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use url::{Position, Url};
+
+/// Well known providers, see <https://oembed.com/providers.json>
+const BUILTIN_PROVIDERS: &[(&[&str], &str)] = &[
+    (
+        &[
+            "https://*.youtube.com/watch*",
+            "https://*.youtube.com/shorts/*",
+            "https://youtu.be/*",
+        ],
+        "https://www.youtube.com/oembed",
+    ),
+    (&["https://vimeo.com/*"], "https://vimeo.com/api/oembed.json"),
+    (
+        &["https://*.flickr.com/photos/*", "https://flic.kr/p/*"],
+        "https://www.flickr.com/services/oembed/",
+    ),
+    (&["https://soundcloud.com/*"], "https://soundcloud.com/oembed"),
+    (&["https://open.spotify.com/*"], "https://open.spotify.com/oembed"),
+];
+
+/// Matches `s` against `pattern`, where `*` in the pattern matches any (possibly empty) sequence of characters
+fn wildcard_matches(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    let Some(first) = parts.next() else {
+        return s.is_empty();
+    };
+
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<_> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // pattern does not contain a wildcard
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Matches `url` against an oEmbed url scheme. The scheme, host and path are compared separately,
+/// so a `*` in the host only matches within a single label
+fn scheme_matches(scheme: &str, url: &Url) -> bool {
+    let Some((protocol, rest)) = scheme.split_once("://") else {
+        return false;
+    };
+
+    let (host, path) = match rest.find('/') {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, "/"),
+    };
+
+    let url_host = &url[Position::BeforeHost..Position::AfterPort];
+    let host_matches = host.split('.').count() == url_host.split('.').count()
+        && host
+            .split('.')
+            .zip(url_host.split('.'))
+            .all(|(pattern, label)| wildcard_matches(pattern, label));
+
+    wildcard_matches(protocol, url.scheme()) && host_matches && wildcard_matches(path, &url[Position::BeforePath..])
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Provider {
+    /// url patterns the provider is responsible for, `*` matches anything within a host label or the path
+    pub schemes: Vec<String>,
+
+    /// the `{format}` placeholder is replaced by `json`
+    pub endpoint: String,
+}
+
+impl Provider {
+    fn matches(&self, url: &Url) -> bool {
+        self.schemes.iter().any(|scheme| scheme_matches(scheme, url))
+    }
+
+    fn request_url(&self, url: &Url) -> anyhow::Result<Url> {
+        let mut endpoint = Url::parse(&self.endpoint.replace("{format}", "json")).context("Invalid oEmbed endpoint")?;
+        endpoint
+            .query_pairs_mut()
+            .append_pair("url", url.as_str())
+            .append_pair("format", "json");
+
+        Ok(endpoint)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiSettings {
+    /// discord already embeds most of the builtin providers with a player, which a repost would replace
+    #[serde(default)]
+    pub builtin_providers: bool,
+
+    /// providers in addition to the builtin ones
+    #[serde(default)]
+    pub providers: Vec<Provider>,
+
+    /// domains (including their subdomains) whose pages are searched for an oEmbed endpoint
+    #[serde(default)]
+    pub discovery_domains: Vec<String>,
+
+    #[serde(default)]
+    pub http: HttpSettings,
+}
+
+/// The subset of an oEmbed response that is relevant for embedding, see <https://oembed.com/#section2.3>
+#[derive(Debug, Deserialize)]
+struct OEmbed {
+    #[serde(rename = "type")]
+    kind: String,
+    title: Option<String>,
+    author_name: Option<String>,
+    provider_name: Option<String>,
+    url: Option<Url>,
+    thumbnail_url: Option<Url>,
+}
+
+pub struct Api {
+    providers: Vec<Provider>,
+    discovery_domains: Vec<String>,
+    http: HttpClient,
+}
+
+impl Api {
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        let builtin = BUILTIN_PROVIDERS
+            .iter()
+            .filter(|_| settings.builtin_providers)
+            .map(|(schemes, endpoint)| Provider {
+                schemes: schemes.iter().map(|s| s.to_string()).collect(),
+                endpoint: endpoint.to_string(),
+            });
+
+        Ok(Self {
+            // configured providers take precedence
+            providers: settings.providers.into_iter().chain(builtin).collect(),
+            discovery_domains: settings.discovery_domains,
+            http: http.with_settings(&settings.http)?,
+        })
+    }
+
+    fn find_provider(&self, url: &Url) -> Option<&Provider> {
+        self.providers.iter().find(|p| p.matches(url))
+    }
+
+    fn discover_endpoint(url: &Url, html: &Html) -> Option<Url> {
+        let selector = Selector::parse(r#"link[rel="alternate"][type="application/json+oembed"]"#).unwrap();
+
+        html.select(&selector)
+            .filter_map(|e| e.attr("href"))
+            .find_map(|href| url.join(href).ok())
+    }
+
+    async fn find_endpoint(&self, url: &Url) -> anyhow::Result<Url> {
+        if let Some(provider) = self.find_provider(url) {
+            return provider.request_url(url);
+        }
+
+        let head = self.http.get_html_head(url.clone()).await?;
+        let html = Html::parse_document(&head);
+
+        let endpoint = Self::discover_endpoint(url, &html)
+            .ok_or_else(|| anyhow::anyhow!("Page does not advertise an oEmbed endpoint"))?;
+
+        anyhow::ensure!(
+            self.is_same_site(url, &endpoint),
+            "Discovered oEmbed endpoint {endpoint} is outside the domain of the page"
+        );
+
+        Ok(endpoint)
+    }
+
+    /// Whether `endpoint` belongs to the same discovery domain as `page`,
+    /// so a page cannot point the bot at arbitrary hosts
+    fn is_same_site(&self, page: &Url, endpoint: &Url) -> bool {
+        self.discovery_domains
+            .iter()
+            .any(|d| url_domain_matches(page, d) && url_domain_matches(endpoint, d))
+    }
+
+    fn analyze_oembed(url: Url, oembed: OEmbed) -> anyhow::Result<Post> {
+        let host = url.host_str().unwrap_or_default();
+        let origin = oembed
+            .provider_name
+            .unwrap_or_else(|| host.strip_prefix("www.").unwrap_or(host).to_owned());
+
+        let specialized = match (oembed.kind.as_str(), oembed.url, oembed.thumbnail_url) {
+            ("photo", Some(img_url), _) => PostSpecializedData::Image { img_url },
+            ("photo", None, _) => anyhow::bail!("oEmbed photo response is missing the photo url"),
+            ("video", _, Some(thumbnail_url)) => PostSpecializedData::VideoThumbnail { thumbnail_url },
+            ("rich" | "link", _, Some(img_url)) => PostSpecializedData::Image { img_url },
+            ("video" | "rich" | "link", _, None) => PostSpecializedData::TextOnly,
+            (kind, _, _) => anyhow::bail!("Unknown oEmbed type: {kind}"),
+        };
+
+        let common = PostCommonData {
+            title: oembed.title.unwrap_or_else(|| origin.clone()),
+            text: oembed
+                .author_name
+                .map(|author| format!("by {author}"))
+                .unwrap_or_default(),
+            src: url,
+            origin,
+            nsfw: false,
+            spoiler: false,
+            comment: None,
+        };
+
+        Ok(Post { common, specialized })
+    }
+}
+
+#[async_trait]
+impl PostScraper for Api {
+    fn is_suitable(&self, url: &Url) -> bool {
+        self.find_provider(url).is_some() || self.discovery_domains.iter().any(|d| url_domain_matches(url, d))
+    }
+
+    async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
+        let endpoint = self.find_endpoint(&url).await?;
+
+        let oembed = self
+            .http
+            .get(endpoint)
+            .await?
+            .json()
+            .await
+            .context("Unable to parse oEmbed response")?;
+
+        Self::analyze_oembed(url, oembed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(url: &str, json: &str) -> Post {
+        Api::analyze_oembed(Url::parse(url).unwrap(), serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn wildcards() {
+        let matches = |scheme: &str, url: &str| scheme_matches(scheme, &Url::parse(url).unwrap());
+
+        assert!(matches(
+            "https://*.youtube.com/watch*",
+            "https://www.youtube.com/watch?v=abc"
+        ));
+        assert!(matches("https://youtu.be/*", "https://youtu.be/abc"));
+        assert!(matches("https://vimeo.com/*", "https://vimeo.com/"));
+        assert!(!matches("https://vimeo.com/*", "https://notvimeo.com/1"));
+        assert!(!matches(
+            "https://*.flickr.com/photos/*",
+            "https://www.flickr.com/groups/1"
+        ));
+        assert!(matches("https://example.com", "https://example.com"));
+        assert!(!matches("https://example.com", "https://example.com/a"));
+    }
+
+    #[test]
+    fn host_wildcards_match_a_single_label() {
+        let matches = |scheme: &str, url: &str| scheme_matches(scheme, &Url::parse(url).unwrap());
+
+        assert!(!matches(
+            "https://*.youtube.com/watch*",
+            "https://evil.com/x.youtube.com/watch"
+        ));
+        assert!(!matches(
+            "https://*.youtube.com/watch*",
+            "https://evil.com?.youtube.com/watch"
+        ));
+        assert!(!matches(
+            "https://*.youtube.com/watch*",
+            "https://a.b.youtube.com/watch"
+        ));
+        assert!(!matches("https://*.youtube.com/watch*", "http://www.youtube.com/watch"));
+    }
+
+    #[test]
+    fn discovered_endpoints_stay_on_the_site() {
+        let api = Api::from_settings(
+            ApiSettings {
+                builtin_providers: false,
+                providers: Vec::new(),
+                discovery_domains: vec!["tumblr.com".to_owned()],
+                http: HttpSettings::default(),
+            },
+            &HttpClient::new().unwrap(),
+        )
+        .unwrap();
+
+        let same_site = |endpoint: &str| {
+            api.is_same_site(
+                &Url::parse("https://someone.tumblr.com/post/1").unwrap(),
+                &Url::parse(endpoint).unwrap(),
+            )
+        };
+
+        assert!(same_site("https://www.tumblr.com/oembed/1.0"));
+        assert!(!same_site("http://169.254.169.254/latest/meta-data"));
+        assert!(!same_site("https://tumblr.com.evil.com/oembed"));
+    }
+
+    #[test]
+    fn provider_request_url() {
+        let provider = Provider {
+            schemes: vec!["https://example.com/*".to_owned()],
+            endpoint: "https://example.com/oembed.{format}".to_owned(),
+        };
+
+        let url = Url::parse("https://example.com/a?b=c").unwrap();
+        assert!(provider.matches(&url));
+        assert_eq!(
+            provider.request_url(&url).unwrap().as_str(),
+            "https://example.com/oembed.json?url=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc&format=json"
+        );
+    }
+
+    #[test]
+    fn endpoint_discovery() {
+        let html = Html::parse_document(
+            r#"<html><head>
+                <link rel="alternate" type="application/json+oembed" href="/oembed?url=x&amp;format=json">
+            </head></html>"#,
+        );
+
+        let url = Url::parse("https://example.com/post/1").unwrap();
+        assert_eq!(
+            Api::discover_endpoint(&url, &html),
+            Some(Url::parse("https://example.com/oembed?url=x&format=json").unwrap())
+        );
+    }
+
+    #[test]
+    fn photo() {
+        let post = analyze(
+            "https://www.flickr.com/photos/someone/1",
+            r#"{
+                "version": "1.0",
+                "type": "photo",
+                "title": "Sunset",
+                "author_name": "someone",
+                "provider_name": "Flickr",
+                "url": "https://live.staticflickr.com/1.jpg",
+                "width": "1024",
+                "height": 768
+            }"#,
+        );
+
+        let expected = Post {
+            common: PostCommonData {
+                src: Url::parse("https://www.flickr.com/photos/someone/1").unwrap(),
+                origin: "Flickr".to_owned(),
+                title: "Sunset".to_owned(),
+                text: "by someone".to_owned(),
+                nsfw: false,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Image {
+                img_url: Url::parse("https://live.staticflickr.com/1.jpg").unwrap(),
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+
+    #[test]
+    fn video() {
+        let post = analyze(
+            "https://www.youtube.com/watch?v=abc",
+            r#"{
+                "version": "1.0",
+                "type": "video",
+                "title": "A video",
+                "html": "<iframe></iframe>",
+                "thumbnail_url": "https://i.ytimg.com/vi/abc/hqdefault.jpg"
+            }"#,
+        );
+
+        assert_eq!(post.common.origin, "youtube.com");
+        assert_eq!(
+            post.specialized,
+            PostSpecializedData::VideoThumbnail {
+                thumbnail_url: Url::parse("https://i.ytimg.com/vi/abc/hqdefault.jpg").unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn rich_without_thumbnail() {
+        let post = analyze(
+            "https://example.com/a",
+            r#"{ "version": "1.0", "type": "rich", "html": "<div></div>", "provider_name": "Example" }"#,
+        );
+
+        assert_eq!(post.common.title, "Example");
+        assert_eq!(post.specialized, PostSpecializedData::TextOnly);
+    }
+}
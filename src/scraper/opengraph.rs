@@ -0,0 +1,264 @@
+#![cfg(feature = "opengraph")]
+
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    util::{url_domain_matches, url_path_ends_with},
+    GalleryImage, Post, PostCommonData, PostScraper, PostSpecializedData,
+};
+use itertools::Itertools;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use url::Url;
+
+/// The `<meta>` tags of a page in document order
+struct MetaTags(Vec<(String, String)>);
+
+impl MetaTags {
+    fn from_html(html: &Html) -> Self {
+        let selector = Selector::parse("meta[content]").unwrap();
+
+        let tags = html
+            .select(&selector)
+            .filter_map(|e| {
+                let key = e.attr("property").or_else(|| e.attr("name"))?;
+                let content = e.attr("content")?.trim();
+
+                (!content.is_empty()).then(|| (key.to_ascii_lowercase(), content.to_owned()))
+            })
+            .collect();
+
+        Self(tags)
+    }
+
+    fn all(&self, key: &'static str) -> impl Iterator<Item = &str> {
+        self.0.iter().filter(move |(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the value of the first of `keys` that is present
+    fn first(&self, keys: &[&'static str]) -> Option<&str> {
+        keys.iter().find_map(|key| self.all(key).next())
+    }
+
+    fn first_url(&self, base: &Url, keys: &[&'static str]) -> Option<Url> {
+        keys.iter()
+            .flat_map(|key| self.all(key))
+            .find_map(|value| base.join(value).ok())
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct ApiSettings {
+    /// domains (including their subdomains) that are embedded using their OpenGraph and Twitter card metadata
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+
+    #[serde(default)]
+    pub http: HttpSettings,
+}
+
+pub struct Api {
+    allowed_domains: Vec<String>,
+    http: HttpClient,
+}
+
+impl Api {
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self {
+            allowed_domains: settings.allowed_domains,
+            http: http.with_settings(&settings.http)?,
+        })
+    }
+
+    fn analyze_page(url: Url, html: &Html) -> anyhow::Result<Post> {
+        let meta = MetaTags::from_html(html);
+
+        let page_title = {
+            let selector = Selector::parse("title").unwrap();
+            html.select(&selector)
+                .next()
+                .map(|e| e.text().collect::<String>().trim().to_owned())
+                .filter(|t| !t.is_empty())
+        };
+
+        let title = meta
+            .first(&["og:title", "twitter:title"])
+            .map(ToOwned::to_owned)
+            .or(page_title);
+        let text = meta.first(&["og:description", "twitter:description", "description"]);
+
+        // secure_url and url are alternative sources for the same image
+        let mut img_urls: Vec<_> = meta
+            .all("og:image:secure_url")
+            .chain(meta.all("og:image"))
+            .chain(meta.all("og:image:url"))
+            .filter_map(|img| url.join(img).ok())
+            .unique()
+            .collect();
+
+        if img_urls.is_empty() {
+            img_urls.extend(meta.first_url(&url, &["twitter:image", "twitter:image:src"]));
+        }
+
+        let video_url = meta.first_url(
+            &url,
+            &[
+                "og:video:secure_url",
+                "og:video:url",
+                "og:video",
+                "twitter:player:stream",
+            ],
+        );
+
+        if title.is_none() && text.is_none() && img_urls.is_empty() && video_url.is_none() {
+            anyhow::bail!("Page does not contain any OpenGraph metadata");
+        }
+
+        let is_mp4 = |video_url: &Url| {
+            url_path_ends_with(video_url, ".mp4")
+                || meta.first(&["og:video:type", "twitter:player:stream:content_type"]) == Some("video/mp4")
+        };
+
+        let specialized = match video_url {
+            Some(video_url) if is_mp4(&video_url) => PostSpecializedData::Video { video_url },
+            Some(_) if !img_urls.is_empty() => {
+                PostSpecializedData::VideoThumbnail { thumbnail_url: img_urls.remove(0) }
+            },
+            _ => match img_urls.len() {
+                0 => PostSpecializedData::TextOnly,
+                1 => PostSpecializedData::Image { img_url: img_urls.remove(0) },
+                _ => PostSpecializedData::Gallery {
+                    images: img_urls.into_iter().map(GalleryImage::from).collect(),
+                },
+            },
+        };
+
+        let host = url.host_str().unwrap_or_default();
+        let origin = meta
+            .first(&["og:site_name"])
+            .unwrap_or_else(|| host.strip_prefix("www.").unwrap_or(host))
+            .to_owned();
+
+        let nsfw = meta.first(&["rating"]).is_some_and(|rating| {
+            rating.eq_ignore_ascii_case("adult") || rating.eq_ignore_ascii_case("RTA-5042-1996-1400-1577-RTA")
+        });
+
+        let common = PostCommonData {
+            title: title.unwrap_or_else(|| origin.clone()),
+            text: text.unwrap_or_default().to_owned(),
+            src: url,
+            origin,
+            nsfw,
+            spoiler: false,
+            comment: None,
+        };
+
+        Ok(Post { common, specialized })
+    }
+}
+
+#[async_trait]
+impl PostScraper for Api {
+    fn is_suitable(&self, url: &Url) -> bool {
+        self.allowed_domains
+            .iter()
+            .any(|domain| url_domain_matches(url, domain))
+    }
+
+    async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
+        // the metadata is all in the head, the rest of the page may be arbitrarily large
+        let head = self.http.get_html_head(url.clone()).await?;
+        let html = Html::parse_document(&head);
+
+        Self::analyze_page(url, &html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(url: &str, html: &str) -> Post {
+        Api::analyze_page(Url::parse(url).unwrap(), &Html::parse_document(html)).unwrap()
+    }
+
+    #[test]
+    fn image_page() {
+        let post = analyze(
+            "https://www.example.com/articles/1",
+            r#"<html><head>
+                <title>Fallback title</title>
+                <meta property="og:title" content="An article">
+                <meta property="og:description" content="About things">
+                <meta property="og:image" content="/img/cover.jpg">
+            </head></html>"#,
+        );
+
+        let expected = Post {
+            common: PostCommonData {
+                src: Url::parse("https://www.example.com/articles/1").unwrap(),
+                origin: "example.com".to_owned(),
+                title: "An article".to_owned(),
+                text: "About things".to_owned(),
+                nsfw: false,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Image {
+                img_url: Url::parse("https://www.example.com/img/cover.jpg").unwrap(),
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+
+    #[test]
+    fn twitter_card_video_page() {
+        let post = analyze(
+            "https://videos.example.com/v/2",
+            r#"<html><head>
+                <title>A video</title>
+                <meta property="og:site_name" content="Example Videos">
+                <meta name="twitter:image" content="https://cdn.example.com/thumb.png">
+                <meta name="twitter:player:stream" content="https://cdn.example.com/video.mp4">
+                <meta name="rating" content="adult">
+            </head></html>"#,
+        );
+
+        assert_eq!(post.common.title, "A video");
+        assert_eq!(post.common.origin, "Example Videos");
+        assert!(post.common.nsfw);
+        assert_eq!(
+            post.specialized,
+            PostSpecializedData::Video {
+                video_url: Url::parse("https://cdn.example.com/video.mp4").unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn embedded_player_falls_back_to_thumbnail() {
+        let post = analyze(
+            "https://videos.example.com/v/3",
+            r#"<html><head>
+                <meta property="og:title" content="A player">
+                <meta property="og:image" content="https://cdn.example.com/thumb.png">
+                <meta property="og:video" content="https://videos.example.com/embed/3">
+                <meta property="og:video:type" content="text/html">
+            </head></html>"#,
+        );
+
+        assert_eq!(
+            post.specialized,
+            PostSpecializedData::VideoThumbnail {
+                thumbnail_url: Url::parse("https://cdn.example.com/thumb.png").unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn page_without_metadata() {
+        let html = Html::parse_document("<html><body>nothing here</body></html>");
+        assert!(Api::analyze_page(Url::parse("https://example.com").unwrap(), &html).is_err());
+    }
+}
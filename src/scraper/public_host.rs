@@ -0,0 +1,196 @@
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use url::{Host, Url};
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Checks whether the url is an https url on the default port of a public looking domain,
+/// i.e. it is safe to send requests to it just because someone posted it
+pub fn is_public_https_url(url: &Url) -> bool {
+    let public_domain = match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            domain.contains('.') && domain != "localhost" && !domain.ends_with(".localhost")
+        },
+        // ip literals are never used to link to posts, but to reach internal services
+        _ => false,
+    };
+
+    url.scheme() == "https" && url.port().is_none() && public_domain
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    // 100.64.0.0/10 is shared between carrier-grade NAT customers
+    let shared = a == 100 && (b & 0b1100_0000) == 64;
+
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || shared)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ip);
+    }
+
+    let first = ip.segments()[0];
+    let unique_local = (first & 0xfe00) == 0xfc00;
+    let link_local = (first & 0xffc0) == 0xfe80;
+
+    !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+/// Resolves host names like the system resolver, but leaves out loopback, private and link-local addresses.
+/// The addresses are checked at connection time, so a host cannot pass a check and then resolve differently.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Only follows redirects to public https urls
+pub fn redirect_policy() -> redirect::Policy {
+    redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !is_public_https_url(attempt.url()) {
+            let error = format!("refusing to follow redirect to {}", attempt.url());
+            attempt.error(error)
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn public_https_urls() {
+        let public = |url: &str| is_public_https_url(&Url::from_str(url).unwrap());
+
+        assert!(public("https://mastodon.social/@alice/1"));
+        assert!(!public("http://mastodon.social/@alice/1"));
+        assert!(!public("https://mastodon.social:8443/@alice/1"));
+        assert!(!public("https://127.0.0.1/@alice/1"));
+        assert!(!public("https://169.254.169.254/@alice/1"));
+        assert!(!public("https://[::1]/@alice/1"));
+        assert!(!public("https://localhost/@alice/1"));
+        assert!(!public("https://metadata.localhost/@alice/1"));
+        assert!(!public("https://intranet/@alice/1"));
+    }
+
+    #[test]
+    fn public_ips() {
+        let public = |ip: &str| is_public_ip(IpAddr::from_str(ip).unwrap());
+
+        assert!(public("1.1.1.1"));
+        assert!(public("2606:4700::1111"));
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(ip), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn loopback_hosts_are_not_resolved() {
+        let resolved = PublicResolver.resolve(Name::from_str("localhost").unwrap()).await;
+        assert!(resolved.is_err());
+    }
+
+    #[tokio::test]
+    async fn redirects_to_internal_hosts_are_not_followed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        // answers every request with a redirect to an internal url on the same server
+        let server = tokio::spawn({
+            let requests = requests.clone();
+
+            async move {
+                loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+
+                    let mut request = vec![0; 1024];
+                    let len = stream.read(&mut request).await.unwrap();
+                    requests
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(&request[..len]).into_owned());
+
+                    let response = format!(
+                        "HTTP/1.1 302 Found\r\nLocation: http://{addr}/internal\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            }
+        });
+
+        let client = reqwest::Client::builder()
+            .redirect(redirect_policy())
+            .no_proxy()
+            .build()
+            .unwrap();
+
+        let resp = client.get(format!("http://{addr}/post")).send().await;
+        server.abort();
+
+        assert!(resp.unwrap_err().is_redirect());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /post "));
+    }
+}
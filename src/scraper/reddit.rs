@@ -1,8 +1,9 @@
 #![cfg(feature = "reddit")]
 
 use crate::scraper::{
-    util::{unescape_html, unescape_url, url_path_ends_with, url_path_ends_with_image_extension, wget, wget_json},
-    Comment, Post, PostCommonData, PostScraper, PostSpecializedData,
+    http::{HttpClient, HttpSettings},
+    util::{unescape_html, unescape_url, url_path_ends_with, url_path_ends_with_image_extension},
+    Comment, GalleryImage, Post, PostCommonData, PostScraper, PostSpecializedData,
 };
 use json_nav::json_nav;
 use reqwest::IntoUrl;
@@ -12,10 +13,10 @@ use serenity::async_trait;
 use std::{borrow::Cow, convert::TryInto};
 use url::Url;
 
-async fn find_canonical_post_url<U: IntoUrl>(post_url: U) -> anyhow::Result<Url> {
+async fn find_canonical_post_url<U: IntoUrl>(http: &HttpClient, post_url: U) -> anyhow::Result<Url> {
     let url = post_url.into_url()?;
 
-    match wget(url.clone()).await {
+    match http.get(url.clone()).await {
         Ok(resp) if resp.url().path() != "/over18" => Ok(resp.url().to_owned()),
         _ => Ok(url),
     }
@@ -30,13 +31,18 @@ fn fmt_title<'t>(title: &'t str, flair: &str) -> Cow<'t, str> {
 }
 
 #[derive(Default, Debug, Deserialize, Serialize)]
-pub struct ApiSettings {}
+pub struct ApiSettings {
+    #[serde(default)]
+    pub http: HttpSettings,
+}
 
-pub struct Api;
+pub struct Api {
+    http: HttpClient,
+}
 
 impl Api {
-    pub fn from_settings(_settings: ApiSettings) -> Self {
-        Self
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        Ok(Self { http: http.with_settings(&settings.http)? })
     }
 
     fn analyze_post(url: Url, json: &Value) -> anyhow::Result<Post> {
@@ -100,8 +106,19 @@ impl Api {
             .and_then(|s| Url::parse(s).map_err(anyhow::Error::from));
 
         let specialized_data = match post_json.get("secure_media") {
-            Some(Value::Object(sm)) if sm.contains_key("reddit_video") => PostSpecializedData::Video {
-                video_url: json_nav! { sm => "reddit_video" => "fallback_url"; as str }?.try_into()?,
+            Some(Value::Object(sm)) if sm.contains_key("reddit_video") => {
+                let video_url = json_nav! { sm => "reddit_video" => "fallback_url"; as str }?.try_into()?;
+
+                // the fallback stream is missing the audio track, which is only available through the manifest
+                let is_gif = json_nav! { sm => "reddit_video" => "is_gif"; as bool }.unwrap_or_default();
+                let manifest_url = json_nav! { sm => "reddit_video" => "dash_url"; as str }
+                    .ok()
+                    .and_then(|u| Url::parse(&unescape_url(u)).ok());
+
+                match manifest_url {
+                    Some(manifest_url) if !is_gif => PostSpecializedData::DashVideo { video_url, manifest_url },
+                    _ => PostSpecializedData::Video { video_url },
+                }
             },
 
             Some(Value::Object(sm)) if sm.contains_key("oembed") => PostSpecializedData::Image {
@@ -112,21 +129,46 @@ impl Api {
 
             _ => {
                 if let Some(Value::Object(meta)) = post_json.get("media_metadata") {
-                    let mut urls = meta
-                        .iter()
-                        .map(|(_key, imgmeta)| {
-                            (json_nav! { imgmeta => "s" => "u"; as str })
-                                .map(unescape_url)
-                                .map_err(anyhow::Error::from)
-                                .and_then(|u| Url::parse(&u).map_err(anyhow::Error::from))
-                        })
-                        .collect::<Result<Vec<_>, _>>()
-                        .map_err(|_| json_nav::JsonNavError::TypeMismatch { expected: "url" })?;
-
-                    if urls.len() == 1 {
-                        PostSpecializedData::Image { img_url: urls.pop().unwrap() }
+                    let img_url = |imgmeta: &Value| {
+                        (json_nav! { imgmeta => "s" => "u"; as str })
+                            .map(unescape_url)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|u| Url::parse(&u).map_err(anyhow::Error::from))
+                            .map_err(|_| json_nav::JsonNavError::TypeMismatch { expected: "url" })
+                    };
+
+                    // media_metadata is keyed by media id, the order chosen by the poster is only in gallery_data
+                    let mut images = match json_nav! { post_json => "gallery_data" => "items" }
+                        .ok()
+                        .and_then(Value::as_array)
+                    {
+                        Some(items) => items
+                            .iter()
+                            .map(|item| {
+                                let media_id = json_nav! { item => "media_id"; as str }?;
+
+                                Ok(GalleryImage {
+                                    url: img_url(meta.get(media_id).ok_or(json_nav::JsonNavError::NotFound)?)?,
+                                    caption: json_nav! { item => "caption"; as str }
+                                        .ok()
+                                        .filter(|c| !c.is_empty())
+                                        .map(unescape_html),
+                                    link: json_nav! { item => "outbound_url"; as str }
+                                        .ok()
+                                        .and_then(|u| Url::parse(&unescape_url(u)).ok()),
+                                })
+                            })
+                            .collect::<Result<Vec<_>, json_nav::JsonNavError>>()?,
+                        None => meta
+                            .values()
+                            .map(|imgmeta| img_url(imgmeta).map(GalleryImage::from))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    };
+
+                    if images.len() == 1 {
+                        PostSpecializedData::Image { img_url: images.pop().unwrap().url }
                     } else {
-                        PostSpecializedData::Gallery { img_urls: urls }
+                        PostSpecializedData::Gallery { images }
                     }
                 } else {
                     let url = Url::parse(json_nav! { post_json => "url"; as str }?).or(alt_embed_url);
@@ -154,14 +196,14 @@ impl PostScraper for Api {
 
     async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
         let (url, json) = {
-            let mut u = find_canonical_post_url(url).await?;
+            let mut u = find_canonical_post_url(&self.http, url).await?;
             u.set_query(None);
 
             let mut get_url = u.clone();
             get_url.set_host(Some("old.reddit.com"))?;
             get_url.set_path(&format!("{}.json", u.path()));
 
-            (u, wget_json(get_url).await?)
+            (u, self.http.get_json(get_url).await?)
         };
 
         Self::analyze_post(url, &json)
@@ -220,8 +262,9 @@ mod tests {
                 spoiler: false,
                 comment: None,
             },
-            specialized: PostSpecializedData::Video {
+            specialized: PostSpecializedData::DashVideo {
                 video_url: Url::from_str("https://v.redd.it/jx4ua6lirla71/DASH_1080.mp4?source=fallback").unwrap(),
+                manifest_url: Url::from_str("https://v.redd.it/jx4ua6lirla71/DASHPlaylist.mpd?a=1628615411%2CNWE4ZTY0NzhhYTc0NGEwZmZjYzlhOWQ4ODdhZDUxN2UwYjVlMDIyMzE3YzM2NWM0YWMyYWIyZmVlZDhlYjc3Ng%3D%3D&v=1&f=sd").unwrap(),
             },
         };
 
@@ -248,13 +291,96 @@ mod tests {
                 comment: None,
             },
             specialized: PostSpecializedData::Gallery {
-                img_urls: vec![
-                    Url::from_str("https://preview.redd.it/nuwtn1ytsha71.jpg?width=3876&format=pjpg&auto=webp&s=7743bf4c3dbdff8e34c5a0a33d5171e4b485e1e5").unwrap(),
-                    Url::from_str("https://preview.redd.it/wrro81ytsha71.jpg?width=4000&format=pjpg&auto=webp&s=5f1a86f3783d7ae290f733083b2af4397332c1be").unwrap(),
+                images: vec![
+                    GalleryImage {
+                        url: Url::from_str("https://preview.redd.it/nuwtn1ytsha71.jpg?width=3876&format=pjpg&auto=webp&s=7743bf4c3dbdff8e34c5a0a33d5171e4b485e1e5").unwrap(),
+                        caption: None,
+                        link: None,
+                    },
+                    GalleryImage {
+                        url: Url::from_str("https://preview.redd.it/wrro81ytsha71.jpg?width=4000&format=pjpg&auto=webp&s=5f1a86f3783d7ae290f733083b2af4397332c1be").unwrap(),
+                        caption: None,
+                        link: None,
+                    },
+                ],
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+
+    #[tokio::test]
+    async fn gallery_post_with_captions_and_links() {
+        // the captured gallery with its items reordered and given a caption and a link, which it did not have
+        const JSON: &str = include_str!("../../test_data/reddit/gallery_captions.synthetic.json");
+        let json: Value = serde_json::from_str(JSON).unwrap();
+
+        let url =
+            "https://www.reddit.com/r/watercooling/comments/ohvv5w/lian_li_o11d_xl_with_2x_3090_sli_triple_radiator/";
+        let post = Api::analyze_post(Url::from_str(url).unwrap(), &json).unwrap();
+
+        let expected = Post {
+            common: PostCommonData {
+                src: Url::from_str("https://www.reddit.com/r/watercooling/comments/ohvv5w/lian_li_o11d_xl_with_2x_3090_sli_triple_radiator/").unwrap(),
+                origin: "reddit.com/r/watercooling".to_owned(),
+                title: "Lian li o11D XL with 2x 3090 SLI triple radiator. done for now will upgrade the motherboard and cpu to threadripper in future. this case is solid! [Build Complete]".to_owned(),
+                text: "".to_owned(),
+                nsfw: false,
+                spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Gallery {
+                images: vec![
+                    GalleryImage {
+                        url: Url::from_str("https://preview.redd.it/wrro81ytsha71.jpg?width=4000&format=pjpg&auto=webp&s=5f1a86f3783d7ae290f733083b2af4397332c1be").unwrap(),
+                        caption: Some("Front view & radiators".to_owned()),
+                        link: None,
+                    },
+                    GalleryImage {
+                        url: Url::from_str("https://preview.redd.it/nuwtn1ytsha71.jpg?width=3876&format=pjpg&auto=webp&s=7743bf4c3dbdff8e34c5a0a33d5171e4b485e1e5").unwrap(),
+                        caption: None,
+                        link: Some(Url::from_str("https://lian-li.com/product/o11d-xl/").unwrap()),
+                    },
                 ],
             },
         };
 
         assert_eq!(expected, post);
     }
+
+    #[test]
+    fn serialization_roundtrip() {
+        const FIXTURES: &[(&str, &str, &str)] = &[
+            (
+                include_str!("../../test_data/reddit/image.json"),
+                "https://www.reddit.com/r/Awwducational/comments/oi687m/a_very_rare_irrawaddy_dolphin_only_92_are/",
+                "image",
+            ),
+            (
+                include_str!("../../test_data/reddit/video.json"),
+                "https://www.reddit.com/r/aww/comments/oi6lfk/mama_cat_wants_her_kitten_to_be_friends_with/",
+                "dash-video",
+            ),
+            (
+                include_str!("../../test_data/reddit/gallery.json"),
+                "https://www.reddit.com/r/watercooling/comments/ohvv5w/lian_li_o11d_xl_with_2x_3090_sli_triple_radiator/",
+                "gallery",
+            ),
+        ];
+
+        for (json, url, kind) in FIXTURES {
+            let json: Value = serde_json::from_str(json).unwrap();
+            let post = Api::analyze_post(Url::from_str(url).unwrap(), &json).unwrap();
+
+            let serialized = serde_json::to_value(&post).unwrap();
+            assert_eq!(serialized["version"], Post::FORMAT_VERSION);
+            assert_eq!(serialized["specialized"]["kind"], *kind);
+
+            let deserialized: Post = serde_json::from_value(serialized).unwrap();
+            assert_eq!(deserialized, post);
+
+            let bytes = rmp_serde::to_vec_named(&post).unwrap();
+            assert_eq!(rmp_serde::from_slice::<Post>(&bytes).unwrap(), post);
+        }
+    }
 }
@@ -1,41 +1,476 @@
 #![cfg(feature = "twitter")]
 
-use crate::scraper::{Post, PostCommonData, PostScraper, PostSpecializedData};
-use headless_chrome::LaunchOptions;
-use scraper::Html;
+mod chrome;
+
+use crate::scraper::{
+    http::{HttpClient, HttpSettings},
+    media_to_specialized,
+    util::{unescape_html, url_path_ends_with},
+    Media, Post, PostCommonData, PostScraper,
+};
+use anyhow::Context;
+#[cfg(feature = "twitter-chrome")]
+use chrome::BrowserPool;
 use serde::{Deserialize, Serialize};
 use serenity::async_trait;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+#[cfg(feature = "twitter-chrome")]
+use std::sync::Arc;
 use url::Url;
 
-fn wget_rendered_html(url: &Url, chrome_executable: Option<&Path>) -> anyhow::Result<Html> {
-    let browser = headless_chrome::Browser::new(
-        LaunchOptions::default_builder()
-            .path(chrome_executable.map(ToOwned::to_owned))
-            .build()
-            .unwrap(),
-    )?;
+/// Tweets as served by the syndication cdn used for embedded tweets
+#[derive(Debug, Deserialize)]
+#[serde(tag = "__typename")]
+enum SyndicationResult {
+    Tweet(Box<SyndicationTweet>),
+
+    #[serde(other)]
+    Unavailable,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyndicationTweet {
+    text: String,
+    user: SyndicationUser,
+
+    #[serde(default)]
+    possibly_sensitive: bool,
+
+    #[serde(default)]
+    entities: SyndicationEntities,
+
+    #[serde(default, rename = "mediaDetails")]
+    media_details: Vec<SyndicationMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyndicationUser {
+    name: String,
+    screen_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SyndicationEntities {
+    #[serde(default)]
+    urls: Vec<SyndicationUrlEntity>,
+
+    #[serde(default)]
+    media: Vec<SyndicationMediaEntity>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyndicationUrlEntity {
+    url: String,
+    expanded_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyndicationMediaEntity {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyndicationMedia {
+    #[serde(rename = "type")]
+    kind: String,
+    media_url_https: Url,
+    video_info: Option<VideoInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoInfo {
+    variants: Vec<VideoVariant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoVariant {
+    content_type: String,
+    url: Url,
+    bitrate: Option<u64>,
+}
+
+impl VideoInfo {
+    /// The mp4 variant with the highest bitrate
+    fn best_mp4(self) -> Option<Url> {
+        self.variants
+            .into_iter()
+            .filter(|v| v.content_type == "video/mp4")
+            .max_by_key(|v| v.bitrate.unwrap_or_default())
+            .map(|v| v.url)
+    }
+}
+
+/// Response of the `/<user>/status/<id>` endpoint of FxTwitter compatible apis
+#[derive(Debug, Deserialize)]
+struct FxResponse {
+    tweet: Option<FxTweet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FxTweet {
+    text: String,
+    author: FxAuthor,
+
+    #[serde(default)]
+    possibly_sensitive: bool,
+
+    media: Option<FxMediaList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FxAuthor {
+    name: String,
+    screen_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FxMediaList {
+    #[serde(default)]
+    all: Vec<FxMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FxMedia {
+    #[serde(rename = "type")]
+    kind: String,
+    url: Url,
+    thumbnail_url: Option<Url>,
+}
+
+/// Extracts the tweet id from urls of the form `/<user>/status/<id>`
+fn tweet_id(url: &Url) -> Option<u64> {
+    let mut segments = url.path_segments()?.skip_while(|s| *s != "status" && *s != "statuses");
+    segments.next()?;
+    segments.next()?.parse().ok()
+}
+
+/// The token the syndication cdn expects alongside the tweet id,
+/// equivalent to javascript's `((id / 1e15) * Math.PI).toString(36).replace(/(0+|\.)/g, "")`
+fn syndication_token(id: u64) -> String {
+    const RADIX: u32 = 36;
+
+    let value = (id as f64 / 1e15) * std::f64::consts::PI;
+
+    // javascript emits the shortest fraction that still identifies the value, this follows v8's DoubleToRadixCString
+    let mut integer = value.trunc() as u64;
+    let mut fraction = value.fract();
+    let mut delta = (0.5 * (value.next_up() - value)).max(0.0f64.next_up());
+    let mut fraction_digits = Vec::new();
+
+    if fraction >= delta {
+        loop {
+            fraction *= f64::from(RADIX);
+            delta *= f64::from(RADIX);
+
+            let digit = fraction as u32;
+            fraction_digits.push(digit);
+            fraction -= f64::from(digit);
+
+            if (fraction > 0.5 || (fraction == 0.5 && digit % 2 == 1)) && fraction + delta > 1.0 {
+                // round up, digits that overflow are dropped and carry into the previous one
+                loop {
+                    match fraction_digits.pop() {
+                        Some(digit) if digit + 1 < RADIX => {
+                            fraction_digits.push(digit + 1);
+                            break;
+                        },
+                        Some(_) => {},
+                        None => {
+                            integer += 1;
+                            break;
+                        },
+                    }
+                }
 
-    let tab = browser.new_tab()?;
-    tab.navigate_to(url.as_str())?;
-    tab.wait_until_navigated()?;
-    let content = tab.get_content()?;
+                break;
+            }
 
-    Ok(Html::parse_document(&content))
+            if fraction < delta {
+                break;
+            }
+        }
+    }
+
+    let mut int_digits = Vec::new();
+    while integer > 0 {
+        int_digits.push((integer % u64::from(RADIX)) as u32);
+        integer /= u64::from(RADIX);
+    }
+
+    int_digits
+        .into_iter()
+        .rev()
+        .chain(fraction_digits)
+        .filter(|&digit| digit != 0)
+        .map(|digit| char::from_digit(digit, RADIX).unwrap())
+        .collect()
+}
+
+fn fmt_author(name: &str, screen_name: &str) -> String {
+    if name.is_empty() {
+        format!("@{screen_name}")
+    } else {
+        format!("{name} (@{screen_name})")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// the cdn serving embedded tweets
+    #[default]
+    Syndication,
+
+    /// an FxTwitter compatible api
+    FxTwitter,
+
+    /// renders the tweet in headless chrome
+    Chrome,
+}
+
+impl Backend {
+    fn default_endpoint(self) -> Option<&'static str> {
+        match self {
+            Self::Syndication => Some("https://cdn.syndication.twimg.com/tweet-result"),
+            Self::FxTwitter => Some("https://api.fxtwitter.com"),
+            Self::Chrome => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct ApiSettings {
+pub struct ChromeSettings {
     pub chrome_executable: Option<PathBuf>,
+
+    /// maximum number of tabs that are kept open for reuse
+    #[serde(default = "ChromeSettings::default_max_tabs")]
+    pub max_tabs: usize,
+
+    #[serde(default = "ChromeSettings::default_max_concurrent_renders")]
+    pub max_concurrent_renders: usize,
+
+    /// the browser is shut down after not being used for this many seconds
+    #[serde(default = "ChromeSettings::default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+}
+
+impl ChromeSettings {
+    fn default_max_tabs() -> usize {
+        2
+    }
+
+    fn default_max_concurrent_renders() -> usize {
+        2
+    }
+
+    fn default_idle_timeout_secs() -> u64 {
+        300
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ApiSettings {
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// overrides the url of the syndication cdn or FxTwitter compatible api
+    pub endpoint: Option<Url>,
+
+    /// render the tweet in headless chrome if the json backend fails, requires the `twitter-chrome` feature
+    #[serde(default)]
+    pub chrome_fallback: bool,
+
+    #[serde(flatten)]
+    pub chrome: ChromeSettings,
+
+    #[serde(default)]
+    pub http: HttpSettings,
 }
 
 pub struct Api {
-    settings: ApiSettings,
+    backend: Backend,
+    endpoint: Option<Url>,
+    http: HttpClient,
+
+    #[cfg(feature = "twitter-chrome")]
+    browser_pool: Option<Arc<BrowserPool>>,
 }
 
 impl Api {
-    pub fn from_settings(settings: ApiSettings) -> Self {
-        Self { settings }
+    pub fn from_settings(settings: ApiSettings, http: &HttpClient) -> anyhow::Result<Self> {
+        let endpoint = match settings.endpoint {
+            Some(endpoint) => Some(endpoint),
+            None => settings
+                .backend
+                .default_endpoint()
+                .map(Url::parse)
+                .transpose()
+                .context("Invalid default endpoint")?,
+        };
+
+        let use_chrome = settings.backend == Backend::Chrome || settings.chrome_fallback;
+
+        #[cfg(not(feature = "twitter-chrome"))]
+        anyhow::ensure!(
+            !use_chrome,
+            "Rendering tweets in chrome requires the twitter-chrome feature"
+        );
+
+        // no render could ever start otherwise
+        anyhow::ensure!(
+            settings.chrome.max_concurrent_renders > 0,
+            "max_concurrent_renders has to be at least 1"
+        );
+
+        Ok(Self {
+            backend: settings.backend,
+            endpoint,
+            http: http.with_settings(&settings.http)?,
+
+            #[cfg(feature = "twitter-chrome")]
+            browser_pool: use_chrome.then(|| Arc::new(BrowserPool::new(settings.chrome))),
+        })
+    }
+
+    fn chrome_fallback(&self) -> bool {
+        #[cfg(feature = "twitter-chrome")]
+        return self.browser_pool.is_some();
+
+        #[cfg(not(feature = "twitter-chrome"))]
+        false
+    }
+
+    fn endpoint(&self) -> anyhow::Result<&Url> {
+        self.endpoint
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Backend does not have an endpoint"))
+    }
+
+    async fn scrape_syndication(&self, url: Url) -> anyhow::Result<Post> {
+        let id = tweet_id(&url).ok_or_else(|| anyhow::anyhow!("Url does not point to a tweet"))?;
+
+        let mut request_url = self.endpoint()?.clone();
+        request_url
+            .query_pairs_mut()
+            .append_pair("id", &id.to_string())
+            .append_pair("token", &syndication_token(id));
+
+        let result = self
+            .http
+            .get(request_url)
+            .await?
+            .json()
+            .await
+            .context("Unable to parse syndication response")?;
+
+        Self::analyze_syndication(url, result)
+    }
+
+    async fn scrape_fxtwitter(&self, url: Url) -> anyhow::Result<Post> {
+        let id = tweet_id(&url).ok_or_else(|| anyhow::anyhow!("Url does not point to a tweet"))?;
+        let user = url
+            .path_segments()
+            .and_then(|mut s| s.next())
+            .ok_or_else(|| anyhow::anyhow!("Url missing first path element"))?;
+
+        let mut request_url = self.endpoint()?.clone();
+        request_url
+            .path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid FxTwitter endpoint"))?
+            .pop_if_empty()
+            .extend([user, "status", &id.to_string()]);
+
+        let resp = self
+            .http
+            .get(request_url)
+            .await?
+            .json()
+            .await
+            .context("Unable to parse FxTwitter response")?;
+
+        Self::analyze_fxtwitter(url, resp)
+    }
+
+    async fn scrape_rendered(&self, url: Url) -> anyhow::Result<Post> {
+        #[cfg(feature = "twitter-chrome")]
+        if let Some(browser_pool) = &self.browser_pool {
+            return browser_pool.scrape(url).await;
+        }
+
+        anyhow::bail!("Unable to render {url}: chrome rendering is disabled")
+    }
+
+    fn analyze_syndication(url: Url, result: SyndicationResult) -> anyhow::Result<Post> {
+        let SyndicationResult::Tweet(tweet) = result else {
+            anyhow::bail!("Tweet is unavailable");
+        };
+
+        let mut text = unescape_html(&tweet.text);
+
+        // media is attached to the tweet, no need to link it
+        for media in &tweet.entities.media {
+            text = text.replace(&media.url, "");
+        }
+
+        for url in &tweet.entities.urls {
+            text = text.replace(&url.url, &url.expanded_url);
+        }
+
+        let media = tweet
+            .media_details
+            .into_iter()
+            .filter_map(|m| match m.kind.as_str() {
+                "photo" => Some(Media::Image(m.media_url_https)),
+                "video" | "animated_gif" => Some(match m.video_info.and_then(VideoInfo::best_mp4) {
+                    Some(video_url) => Media::Video(video_url),
+                    None => Media::VideoThumbnail(m.media_url_https),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Post {
+            common: PostCommonData {
+                text: text.trim().to_owned(),
+                nsfw: tweet.possibly_sensitive,
+                spoiler: false,
+                src: url,
+                origin: "twitter.com".to_owned(),
+                title: fmt_author(&tweet.user.name, &tweet.user.screen_name),
+                comment: None,
+            },
+            specialized: media_to_specialized(media),
+        })
+    }
+
+    fn analyze_fxtwitter(url: Url, resp: FxResponse) -> anyhow::Result<Post> {
+        let tweet = resp.tweet.ok_or_else(|| anyhow::anyhow!("Tweet is unavailable"))?;
+
+        let media = tweet
+            .media
+            .map(|m| m.all)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|m| match m.kind.as_str() {
+                "photo" => Some(Media::Image(m.url)),
+                "video" | "gif" if url_path_ends_with(&m.url, ".mp4") => Some(Media::Video(m.url)),
+                "video" | "gif" => m.thumbnail_url.map(Media::VideoThumbnail),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Post {
+            common: PostCommonData {
+                text: tweet.text,
+                nsfw: tweet.possibly_sensitive,
+                spoiler: false,
+                src: url,
+                origin: "twitter.com".to_owned(),
+                title: fmt_author(&tweet.author.name, &tweet.author.screen_name),
+                comment: None,
+            },
+            specialized: media_to_specialized(media),
+        })
     }
 }
 
@@ -46,71 +481,117 @@ impl PostScraper for Api {
     }
 
     async fn scrape_post(&self, url: Url) -> anyhow::Result<Post> {
-        let chrome_exec = self.settings.chrome_executable.clone();
+        let result = match self.backend {
+            Backend::Syndication => self.scrape_syndication(url.clone()).await,
+            Backend::FxTwitter => self.scrape_fxtwitter(url.clone()).await,
+            Backend::Chrome => return self.scrape_rendered(url).await,
+        };
+
+        match result {
+            Err(e) if self.chrome_fallback() => {
+                tracing::warn!(
+                    "Unable to scrape {url} using {:?} backend: {e:#}, falling back to chrome",
+                    self.backend
+                );
+                self.scrape_rendered(url).await
+            },
+            result => result,
+        }
+    }
+}
 
-        let post = tokio::task::spawn_blocking(move || {
-            let author = url
-                .path_segments()
-                .ok_or_else(|| anyhow::anyhow!("Url missing path"))?
-                .next()
-                .ok_or_else(|| anyhow::anyhow!("Url missing first path element"))?
-                .to_owned();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scraper::PostSpecializedData;
 
-            let html = wget_rendered_html(&url, chrome_exec.as_deref())?;
+    #[test]
+    fn tweet_ids() {
+        let id = |url: &str| tweet_id(&Url::parse(url).unwrap());
 
-            let text = {
-                let selector = scraper::Selector::parse(r#"article div[data-testid="tweetText"]"#).unwrap();
+        assert_eq!(
+            id("https://twitter.com/carol/status/1786000000000000000"),
+            Some(1786000000000000000)
+        );
+        assert_eq!(
+            id("https://x.com/carol/status/1786000000000000000/photo/1?s=20"),
+            Some(1786000000000000000)
+        );
+        assert_eq!(id("https://twitter.com/i/web/status/12345"), Some(12345));
+        assert_eq!(id("https://twitter.com/carol"), None);
+    }
+
+    #[test]
+    fn token() {
+        // produced by the javascript expression in the doc comment of `syndication_token`
+        assert_eq!(syndication_token(1786000000000000000), "4buvua9lpjr");
+        assert_eq!(syndication_token(1544817045786759168), "3qt6owioun4");
+        assert_eq!(syndication_token(463440424141459456), "14fxvks611f");
+        assert_eq!(syndication_token(1234567890123456789), "2zqic77uqyk");
+        assert_eq!(syndication_token(20), "6dq1a2xwd93");
+        assert_eq!(syndication_token(1), "bhi2ay3f28n");
+    }
 
-                html.select(&selector)
-                    .next()
-                    .map(|e| e.text().filter(|&s| s != "…").collect())
-                    .unwrap_or_default()
-            };
+    #[test]
+    fn syndication_video() {
+        const JSON: &str = include_str!("../../test_data/twitter/syndication.json");
 
-            let common = PostCommonData {
-                text,
+        let url = Url::parse("https://x.com/carol/status/1786000000000000000").unwrap();
+        let post = Api::analyze_syndication(url.clone(), serde_json::from_str(JSON).unwrap()).unwrap();
+
+        let expected = Post {
+            common: PostCommonData {
+                src: url,
+                origin: "twitter.com".to_owned(),
+                title: "Carol (@carol)".to_owned(),
+                text: "look at this & read https://example.com/article".to_owned(),
                 nsfw: false,
                 spoiler: false,
+                comment: None,
+            },
+            specialized: PostSpecializedData::Video {
+                video_url: Url::parse("https://video.twimg.com/ext_tw_video/1/pu/vid/1280x720/high.mp4").unwrap(),
+            },
+        };
+
+        assert_eq!(expected, post);
+    }
+
+    #[test]
+    fn syndication_tombstone() {
+        let result = serde_json::from_str(r#"{ "__typename": "TweetTombstone", "tombstone": {} }"#).unwrap();
+        assert!(Api::analyze_syndication(Url::parse("https://x.com/a/status/1").unwrap(), result).is_err());
+    }
+
+    #[test]
+    fn fxtwitter_gallery() {
+        const JSON: &str = include_str!("../../test_data/twitter/fxtwitter.json");
+
+        let url = Url::parse("https://twitter.com/dave/status/1786000000000000001").unwrap();
+        let post = Api::analyze_fxtwitter(url.clone(), serde_json::from_str(JSON).unwrap()).unwrap();
+
+        let expected = Post {
+            common: PostCommonData {
                 src: url,
                 origin: "twitter.com".to_owned(),
-                title: format!("@{author}"),
+                title: "Dave (@dave)".to_owned(),
+                text: "two pictures".to_owned(),
+                nsfw: true,
+                spoiler: false,
                 comment: None,
-            };
-
-            let mut img_urls: Vec<_> = {
-                let selector = scraper::Selector::parse(r#"article img[alt]:not([alt=""])"#).unwrap();
-
-                html.select(&selector)
-                    .filter_map(|e| e.attr("src"))
-                    .filter(|src| src.starts_with("https://pbs.twimg.com/media"))
-                    .filter_map(|s| Url::parse(s).ok())
-                    .collect()
-            };
-
-            let specialized = match img_urls.len() {
-                0 => {
-                    let selector = scraper::Selector::parse("article video").unwrap();
-
-                    if let Some(video) = html.select(&selector).next() {
-                        if matches!(video.attr("type"), Some("video/mp4")) {
-                            let src = video.attr("src").unwrap();
-                            PostSpecializedData::Video { video_url: Url::parse(src)? }
-                        } else {
-                            let poster = video.attr("poster").unwrap();
-                            PostSpecializedData::VideoThumbnail { thumbnail_url: Url::parse(poster)? }
-                        }
-                    } else {
-                        PostSpecializedData::TextOnly
-                    }
-                },
-                1 => PostSpecializedData::Image { img_url: img_urls.swap_remove(0) },
-                _ => PostSpecializedData::Gallery { img_urls },
-            };
-
-            Ok::<_, anyhow::Error>(Post { common, specialized })
-        })
-        .await??;
+            },
+            specialized: PostSpecializedData::Gallery {
+                images: vec![
+                    Url::parse("https://pbs.twimg.com/media/first.jpg?name=orig")
+                        .unwrap()
+                        .into(),
+                    Url::parse("https://pbs.twimg.com/media/second.jpg?name=orig")
+                        .unwrap()
+                        .into(),
+                ],
+            },
+        };
 
-        Ok(post)
+        assert_eq!(expected, post);
     }
 }
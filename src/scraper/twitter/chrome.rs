@@ -0,0 +1,259 @@
+#![cfg(feature = "twitter-chrome")]
+
+use super::ChromeSettings;
+use crate::scraper::{GalleryImage, Post, PostCommonData, PostSpecializedData};
+use headless_chrome::{Browser, LaunchOptions, Tab};
+use scraper::Html;
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+use url::Url;
+
+struct PooledBrowser {
+    browser: Browser,
+    idle_tabs: Vec<Arc<Tab>>,
+    last_used: Instant,
+}
+
+impl PooledBrowser {
+    fn launch(settings: &ChromeSettings) -> anyhow::Result<Self> {
+        tracing::debug!("Launching browser");
+
+        let browser = Browser::new(
+            LaunchOptions::default_builder()
+                .path(settings.chrome_executable.clone())
+                // idle shutdown is handled by the pool, this only needs to be longer than that
+                .idle_browser_timeout(Duration::from_secs(settings.idle_timeout_secs.saturating_add(60)))
+                .build()
+                .unwrap(),
+        )?;
+
+        Ok(Self { browser, idle_tabs: Vec::new(), last_used: Instant::now() })
+    }
+}
+
+/// A lazily launched browser that is shared between all renders, shut down after being idle for a while
+/// and relaunched if it crashed
+pub(super) struct BrowserPool {
+    settings: ChromeSettings,
+    render_permits: Semaphore,
+    browser: Arc<Mutex<Option<PooledBrowser>>>,
+}
+
+impl BrowserPool {
+    pub(super) fn new(settings: ChromeSettings) -> Self {
+        let browser = Arc::new(Mutex::new(None));
+
+        tokio::spawn(Self::shutdown_when_idle(
+            Arc::downgrade(&browser),
+            Duration::from_secs(settings.idle_timeout_secs),
+        ));
+
+        Self {
+            render_permits: Semaphore::new(settings.max_concurrent_renders),
+            settings,
+            browser,
+        }
+    }
+
+    async fn shutdown_when_idle(browser: Weak<Mutex<Option<PooledBrowser>>>, idle_timeout: Duration) {
+        let check_interval = (idle_timeout / 2).max(Duration::from_secs(1));
+
+        loop {
+            tokio::time::sleep(check_interval).await;
+
+            let Some(browser) = browser.upgrade() else {
+                // pool was dropped
+                break;
+            };
+
+            // the lock is shared with the blocking renders and killing the browser process blocks as well
+            let _ = tokio::task::spawn_blocking(move || {
+                let idle = browser
+                    .lock()
+                    .unwrap()
+                    .take_if(|b| b.last_used.elapsed() >= idle_timeout);
+
+                if idle.is_some() {
+                    tracing::debug!("Shutting down idle browser");
+                }
+            })
+            .await;
+        }
+    }
+
+    fn checkout_tab(&self) -> anyhow::Result<(Browser, Arc<Tab>)> {
+        // talking to the browser can take seconds, so the lock is only held to look at the pool
+        let pooled = self.browser.lock().unwrap().as_mut().map(|pooled| {
+            pooled.last_used = Instant::now();
+            (pooled.browser.clone(), pooled.idle_tabs.pop())
+        });
+
+        let (browser, tab) = match pooled {
+            Some((browser, tab)) if browser.get_version().is_ok() => (browser, tab),
+            Some((unresponsive, _)) => {
+                tracing::warn!("Browser is unresponsive, restarting it");
+                self.discard(&unresponsive);
+                (self.launch()?, None)
+            },
+            None => (self.launch()?, None),
+        };
+
+        let tab = match tab {
+            Some(tab) => tab,
+            None => browser.new_tab()?,
+        };
+
+        Ok((browser, tab))
+    }
+
+    /// Launches a browser for the pool, or returns the one a concurrent render launched in the meantime
+    fn launch(&self) -> anyhow::Result<Browser> {
+        let launched = PooledBrowser::launch(&self.settings)?;
+        let mut pooled = self.browser.lock().unwrap();
+
+        match &mut *pooled {
+            Some(existing) => {
+                existing.last_used = Instant::now();
+                Ok(existing.browser.clone())
+            },
+            None => Ok(pooled.insert(launched).browser.clone()),
+        }
+    }
+
+    /// Removes `browser` from the pool, unless it was already replaced
+    fn discard(&self, browser: &Browser) {
+        let discarded = self
+            .browser
+            .lock()
+            .unwrap()
+            .take_if(|b| b.browser.get_process_id() == browser.get_process_id());
+
+        // only kill the browser process after releasing the lock
+        drop(discarded);
+    }
+
+    fn checkin_tab(&self, browser: &Browser, tab: Arc<Tab>) {
+        let mut pooled = self.browser.lock().unwrap();
+
+        match &mut *pooled {
+            Some(pooled)
+                if pooled.browser.get_process_id() == browser.get_process_id()
+                    && pooled.idle_tabs.len() < self.settings.max_tabs =>
+            {
+                pooled.last_used = Instant::now();
+                pooled.idle_tabs.push(tab);
+            },
+            _ => {
+                let _ = tab.close(false);
+            },
+        }
+    }
+
+    fn try_render_blocking(&self, url: &Url) -> anyhow::Result<String> {
+        let (browser, tab) = self.checkout_tab()?;
+
+        let content = tab
+            .navigate_to(url.as_str())
+            .and_then(|tab| tab.wait_until_navigated())
+            .and_then(|tab| tab.get_content());
+
+        match content {
+            Ok(content) => {
+                self.checkin_tab(&browser, tab);
+                Ok(content)
+            },
+            Err(e) => {
+                let _ = tab.close(false);
+                Err(e)
+            },
+        }
+    }
+
+    fn render_blocking(&self, url: &Url) -> anyhow::Result<String> {
+        self.try_render_blocking(url).or_else(|e| {
+            // a crashed browser is only noticed on use, give it one more try with a fresh browser
+            tracing::warn!("Unable to render {url}: {e:#}, retrying");
+            self.try_render_blocking(url)
+        })
+    }
+
+    async fn render(self: &Arc<Self>, url: &Url) -> anyhow::Result<Html> {
+        let _permit = self.render_permits.acquire().await?;
+
+        let pool = self.clone();
+        let url = url.clone();
+        let content = tokio::task::spawn_blocking(move || pool.render_blocking(&url)).await??;
+
+        Ok(Html::parse_document(&content))
+    }
+
+    pub(super) async fn scrape(self: &Arc<Self>, url: Url) -> anyhow::Result<Post> {
+        let html = self.render(&url).await?;
+        analyze_rendered(url, &html)
+    }
+}
+
+fn analyze_rendered(url: Url, html: &Html) -> anyhow::Result<Post> {
+    let author = url
+        .path_segments()
+        .ok_or_else(|| anyhow::anyhow!("Url missing path"))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Url missing first path element"))?
+        .to_owned();
+
+    let text = {
+        let selector = scraper::Selector::parse(r#"article div[data-testid="tweetText"]"#).unwrap();
+
+        html.select(&selector)
+            .next()
+            .map(|e| e.text().filter(|&s| s != "…").collect())
+            .unwrap_or_default()
+    };
+
+    let common = PostCommonData {
+        text,
+        nsfw: false,
+        spoiler: false,
+        src: url,
+        origin: "twitter.com".to_owned(),
+        title: format!("@{author}"),
+        comment: None,
+    };
+
+    let mut img_urls: Vec<_> = {
+        let selector = scraper::Selector::parse(r#"article img[alt]:not([alt=""])"#).unwrap();
+
+        html.select(&selector)
+            .filter_map(|e| e.attr("src"))
+            .filter(|src| src.starts_with("https://pbs.twimg.com/media"))
+            .filter_map(|s| Url::parse(s).ok())
+            .collect()
+    };
+
+    let specialized = match img_urls.len() {
+        0 => {
+            let selector = scraper::Selector::parse("article video").unwrap();
+
+            if let Some(video) = html.select(&selector).next() {
+                if matches!(video.attr("type"), Some("video/mp4")) {
+                    let src = video.attr("src").unwrap();
+                    PostSpecializedData::Video { video_url: Url::parse(src)? }
+                } else {
+                    let poster = video.attr("poster").unwrap();
+                    PostSpecializedData::VideoThumbnail { thumbnail_url: Url::parse(poster)? }
+                }
+            } else {
+                PostSpecializedData::TextOnly
+            }
+        },
+        1 => PostSpecializedData::Image { img_url: img_urls.swap_remove(0) },
+        _ => PostSpecializedData::Gallery {
+            images: img_urls.into_iter().map(GalleryImage::from).collect(),
+        },
+    };
+
+    Ok(Post { common, specialized })
+}
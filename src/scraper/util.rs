@@ -1,27 +1,10 @@
-use anyhow::Context;
-use reqwest::IntoUrl;
 use url::Url;
 
-const USER_AGENT: &str = concat!("github.com/liss-h/embedbot-rs embedbot/", clap::crate_version!());
-
-pub async fn wget<U: IntoUrl>(url: U) -> anyhow::Result<reqwest::Response> {
-    let client = reqwest::Client::new();
-    client
-        .get(url)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await
-        .context("Unable to fetch web page")?
-        .error_for_status()
-        .context("Server responded with error code")
-}
-
-pub async fn wget_json<U: IntoUrl>(url: U) -> anyhow::Result<serde_json::Value> {
-    wget(url)
-        .await?
-        .json()
-        .await
-        .context("Unable to parse response as json")
+/// Checks whether the url points to `domain` or one of its subdomains
+pub fn url_domain_matches(url: &Url, domain: &str) -> bool {
+    url.domain()
+        .and_then(|d| d.strip_suffix(domain))
+        .is_some_and(|subdomain| subdomain.is_empty() || subdomain.ends_with('.'))
 }
 
 pub fn url_path_ends_with(haystack: &Url, needle: &str) -> bool {